@@ -12,23 +12,264 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use serde::{Deserialize, Serialize};
+
 /// The worst change present in the working tree.
 ///
-/// In increasing order of severity: `Conflicted` > `Unstaged` > `Staged` >
-/// `NotChanged`. This precedence is a domain decision applied explicitly by
+/// In increasing order of severity: `Conflicted` > `Unstaged` > `Untracked` >
+/// `Staged` > `NotChanged`. This precedence is a domain decision applied explicitly by
 /// [`Repository::branch_status`](crate::repository::Repository::branch_status),
 /// not derived from this declaration order, so the variants can be reordered
 /// freely without changing behavior.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Status {
     NotChanged,
     Staged,
+    /// Only reported when untracked files are enabled with
+    /// [`Repository::untracked_files`](crate::repository::Repository::untracked_files).
+    Untracked,
     Unstaged,
     Conflicted,
+    /// The scan was cancelled before the status could be determined, e.g. by
+    /// `--timeout`. It is not part of the severity order above.
+    Unknown,
+    /// Detecting the status failed, e.g. because the index is corrupt. Like
+    /// `Unknown`, it is not part of the severity order above.
+    Failed,
+}
+
+impl Status {
+    /// The `snake_case` name, as used in the JSON output.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::NotChanged => "not_changed",
+            Self::Staged => "staged",
+            Self::Untracked => "untracked",
+            Self::Unstaged => "unstaged",
+            Self::Conflicted => "conflicted",
+            Self::Unknown => "unknown",
+            Self::Failed => "failed",
+        }
+    }
+
+    /// The marker appended to the branch label when color is off, so the
+    /// status is still visible. Empty when nothing changed.
+    #[must_use]
+    pub const fn marker(&self) -> &'static str {
+        match self {
+            Self::NotChanged => "",
+            Self::Staged => "+",
+            Self::Untracked => "?",
+            Self::Unstaged => "*",
+            Self::Conflicted => "!",
+            Self::Unknown => "…",
+            Self::Failed => "✗",
+        }
+    }
+}
+
+/// The remote-tracking branch configured as the upstream of the current branch
+/// (`branch.<name>.remote` / `branch.<name>.merge`), and how far the two have
+/// diverged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Upstream {
+    /// The shorthand of the remote-tracking ref (e.g. `origin/main`).
+    pub name: String,
+    /// Commits reachable from the local branch but not from the upstream.
+    pub ahead: usize,
+    /// Commits reachable from the upstream but not from the local branch.
+    pub behind: usize,
 }
 
+/// The remote of an upstream, as configured by `branch.<name>.remote` and
+/// `branch.<name>.merge`.
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    /// The name of the remote (e.g. `origin`).
+    pub name: String,
+    /// The branch on the remote (e.g. `main`).
+    pub branch: String,
+    /// The fetch URL of the remote, if configured.
+    pub url: Option<String>,
+}
+
+/// The number of changed paths in each category, as counted by
+/// [`Repository::changes`](crate::repository::Repository::changes).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Changes {
+    /// Paths whose index entry differs from HEAD.
+    pub staged: usize,
+    /// Paths modified in the worktree but not staged, excluding deletions.
+    pub unstaged: usize,
+    /// Paths with unmerged index entries.
+    pub conflicted: usize,
+    /// Paths deleted from the worktree but not staged.
+    pub deleted: usize,
+    /// Untracked files, or zero unless untracked files are enabled.
+    pub untracked: usize,
+}
+
+impl Changes {
+    /// The worst [`Status`] these changes amount to, with the same precedence
+    /// as [`Repository::branch_status`](crate::repository::Repository::branch_status).
+    #[must_use]
+    pub const fn status(&self) -> Status {
+        if self.conflicted > 0 {
+            Status::Conflicted
+        } else if self.unstaged > 0 || self.deleted > 0 {
+            Status::Unstaged
+        } else if self.untracked > 0 {
+            Status::Untracked
+        } else if self.staged > 0 {
+            Status::Staged
+        } else {
+            Status::NotChanged
+        }
+    }
+}
+
+/// How far a multi-step operation such as a rebase has got.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    /// The step being applied, starting at 1.
+    pub current: usize,
+    /// The total number of steps.
+    pub total: usize,
+}
+
+/// What HEAD points at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeadKind {
+    /// HEAD is a symbolic ref to an existing branch.
+    Symbolic,
+    /// HEAD is a symbolic ref to a branch without any commits yet.
+    Unborn,
+    /// HEAD points directly at a commit.
+    Detached,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Branch {
+    /// The branch name, or for a detached HEAD a tag or short hash. During a
+    /// rebase this is the branch being rebased.
     pub name: String,
+    /// The in-progress action (e.g. `rebase-i`), if any.
+    pub action: Option<String>,
+    /// The progress of the action, for a rebase or `git am`.
+    pub progress: Option<Progress>,
+    /// The kind of the actual HEAD, which is detached during a rebase even
+    /// though [`name`](Self::name) shows the branch being rebased.
+    pub head: HeadKind,
     pub status: Status,
+    /// `None` when HEAD is detached or unborn, no upstream is configured, or the
+    /// remote-tracking ref has not been fetched yet.
+    pub upstream: Option<Upstream>,
+    /// The number of stash entries.
+    pub stash: usize,
+    /// Per-category change counts, only computed when requested since they
+    /// require walking the whole worktree.
+    pub changes: Option<Changes>,
+}
+
+impl Branch {
+    /// The name as shown in prompts, suffixed with the in-progress action and
+    /// its progress if any (e.g. `main:rebase-i 3/12`).
+    #[must_use]
+    pub fn label(&self) -> String {
+        self.name.clone() + &self.action_label()
+    }
+
+    /// The part of [`Self::label`] after the name, e.g. `:rebase-i 3/12`, or
+    /// empty when no action is in progress.
+    #[must_use]
+    pub fn action_label(&self) -> String {
+        let Some(action) = &self.action else {
+            return String::new();
+        };
+        let progress = self
+            .progress
+            .as_ref()
+            .map(|progress| format!(" {}/{}", progress.current, progress.total))
+            .unwrap_or_default();
+        format!(":{action}{progress}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::branch;
+
+    #[test]
+    fn test_label_with_action_and_progress() {
+        let branch = Branch {
+            action: Some("rebase-i".to_owned()),
+            progress: Some(Progress {
+                current: 3,
+                total: 12,
+            }),
+            ..branch("feature")
+        };
+        assert_eq!(branch.label(), "feature:rebase-i 3/12");
+    }
+
+    #[test]
+    fn test_changes_status_is_not_changed_when_empty() {
+        assert_eq!(Changes::default().status(), Status::NotChanged);
+    }
+
+    #[test]
+    fn test_changes_status_prefers_worst_category() {
+        let changes = Changes {
+            staged: 1,
+            untracked: 1,
+            ..Changes::default()
+        };
+        assert_eq!(changes.status(), Status::Untracked);
+        let changes = Changes {
+            deleted: 1,
+            ..changes
+        };
+        assert_eq!(changes.status(), Status::Unstaged);
+        let changes = Changes {
+            conflicted: 1,
+            ..changes
+        };
+        assert_eq!(changes.status(), Status::Conflicted);
+    }
+
+    #[test]
+    fn test_label_without_action() {
+        let branch = branch("main");
+        assert_eq!(branch.label(), "main");
+    }
+
+    #[test]
+    fn test_label_with_action() {
+        let branch = Branch {
+            action: Some("rebase-i".to_owned()),
+            ..branch("main")
+        };
+        assert_eq!(branch.label(), "main:rebase-i");
+    }
+
+    #[test]
+    fn test_status_name_matches_json() -> Result<(), serde_json::Error> {
+        for status in [
+            Status::NotChanged,
+            Status::Staged,
+            Status::Untracked,
+            Status::Unstaged,
+            Status::Conflicted,
+            Status::Unknown,
+            Status::Failed,
+        ] {
+            let json = serde_json::to_string(&status)?;
+            assert_eq!(json, format!("\"{}\"", status.name()));
+        }
+        Ok(())
+    }
 }
@@ -12,11 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::iter;
 use std::path::PathBuf;
+use std::sync::LazyLock;
 
-use clap::{Parser, ValueHint};
+use clap::builder::PossibleValue;
+use clap::{Parser, Subcommand, ValueEnum, ValueHint};
 
+use crate::color::{ColorChoice, Palette};
+use crate::detect;
+use crate::gitstatusd;
+use crate::init::Shell;
 use crate::mode::Mode;
+use crate::template::Template;
+use crate::vcs_info;
 
 #[derive(Parser)]
 #[command(
@@ -29,15 +38,145 @@ use crate::mode::Mode;
 )]
 #[non_exhaustive]
 pub struct Cli {
-    /// Sets a mode
-    #[arg(short, long, value_enum, default_value_t = Mode::Stdout)]
-    pub mode: Mode,
+    /// Sets a mode. `auto` picks one for the calling shell
+    #[arg(
+        short,
+        long,
+        value_enum,
+        env = "GIT_BRANCH_STATUS_MODE",
+        default_value_t = ModeArg::Mode(Mode::Stdout)
+    )]
+    pub mode: ModeArg,
+
+    /// Colors the output: `auto` follows `NO_COLOR`, `CLICOLOR_FORCE` and
+    /// git's `color.ui`. Without color, the status is shown as a marker
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Overrides colors with comma-separated `<key>=<color>` pairs, e.g.
+    /// `staged=136,unstaged=#dc322f,action=magenta`. Keys are the statuses
+    /// and `action`; colors are names, 256-color indexes or `#rrggbb`
+    #[arg(long, env = "GIT_BRANCH_STATUS_COLORS", value_name = "PALETTE")]
+    pub colors: Option<Palette>,
+
+    /// Reports untracked files as their own status, unless the repository sets
+    /// `status.showUntrackedFiles` to `no` or `false`
+    #[arg(short, long)]
+    pub untracked_files: bool,
+
+    /// Shows the number of staged, unstaged, deleted, conflicted and untracked
+    /// files. Slower, since the whole worktree is scanned
+    #[arg(short, long)]
+    pub counts: bool,
+
+    /// Gives up on the status after this many milliseconds and shows the
+    /// branch in a distinct color instead
+    #[arg(short, long, value_name = "MS")]
+    pub timeout: Option<u64>,
+
+    /// Formats the output with a template instead of the built-in format,
+    /// e.g. `{branch:status}[:{action}][ ↑{ahead}][ ↓{behind}]`
+    #[arg(short, long, value_name = "TEMPLATE")]
+    pub format: Option<Template>,
+
+    #[command(flatten)]
+    pub vcs_info: vcs_info::Options,
+
+    /// The socket of a running `daemon` to ask, instead of scanning the
+    /// worktree. Without a daemon listening, the worktree is scanned directly
+    #[cfg(unix)]
+    #[arg(
+        long,
+        global = true,
+        env = "GIT_BRANCH_STATUS_SOCKET",
+        value_name = "PATH",
+        value_hint = ValueHint::FilePath
+    )]
+    pub socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
     /// Path to the git repository (default: current directory)
-    #[arg(value_name = "DIR", value_hint = ValueHint::DirPath, default_value = ".")]
+    #[arg(value_name = "DIR", global = true, value_hint = ValueHint::DirPath, default_value = ".")]
     pub dir: PathBuf,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+#[non_exhaustive]
+pub enum Command {
+    /// Prints a script that shows the branch in the shell's prompt, e.g.
+    /// `eval "$(git branch-status init zsh)"`
+    Init {
+        /// The shell to integrate with
+        #[arg(value_enum)]
+        shell: Shell,
+
+        /// Updates the prompt in the background instead of blocking it (zsh
+        /// only)
+        #[arg(long = "async")]
+        asynchronous: bool,
+    },
+
+    /// Prints the status like `git status --porcelain=v2 --branch`, and fails
+    /// if git could report a rename unless `status.renames` is false
+    Porcelain,
+
+    /// Prints the branch like `__git_ps1` from git's `git-prompt.sh`,
+    /// following the exported `GIT_PS1_*` variables
+    Ps1 {
+        /// Where the branch and its state go, as `%s`. `%%` is a literal `%`
+        #[arg(value_name = "FORMAT", default_value = " (%s)")]
+        format: String,
+    },
+
+    /// Answers gitstatusd requests on stdin, so gitstatus and powerlevel10k
+    /// can use it as `GITSTATUS_DAEMON`
+    Gitstatusd(Box<gitstatusd::Options>),
+
+    /// Keeps repositories open and watched, answering queries on a Unix
+    /// socket so prompts do not scan the worktree each time
+    #[cfg(unix)]
+    Daemon,
+}
+
+/// The value of `--mode`: a [`Mode`], or `auto` to detect it at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModeArg {
+    Auto,
+    Mode(Mode),
+}
+
+/// `auto` followed by every [`Mode`], in declaration order.
+static MODE_ARGS: LazyLock<Vec<ModeArg>> = LazyLock::new(|| {
+    let modes = Mode::value_variants().iter().cloned().map(ModeArg::Mode);
+    iter::once(ModeArg::Auto).chain(modes).collect()
+});
+
+impl ModeArg {
+    /// The mode to format with, detecting the calling shell for `auto`.
+    #[must_use]
+    pub fn resolve(self) -> Mode {
+        match self {
+            Self::Auto => detect::mode(),
+            Self::Mode(mode) => mode,
+        }
+    }
+}
+
+impl ValueEnum for ModeArg {
+    fn value_variants<'a>() -> &'a [Self] {
+        &MODE_ARGS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            Self::Auto => Some(PossibleValue::new("auto")),
+            Self::Mode(mode) => mode.to_possible_value(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use clap::CommandFactory as _;
@@ -48,4 +187,16 @@ mod tests {
     fn command() {
         Cli::command().debug_assert();
     }
+
+    #[test]
+    fn test_mode_accepts_auto() {
+        let cli = Cli::parse_from(["git-branch-status", "--mode", "auto"]);
+        assert_eq!(cli.mode, ModeArg::Auto);
+    }
+
+    #[test]
+    fn test_mode_accepts_mode_and_alias() {
+        let cli = Cli::parse_from(["git-branch-status", "--mode", "pwsh"]);
+        assert_eq!(cli.mode, ModeArg::Mode(Mode::PowerShell));
+    }
 }
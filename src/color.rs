@@ -0,0 +1,483 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use gix::bstr::BStr;
+use gix::config::Boolean;
+
+use crate::branch::Status;
+
+/// A foreground color, rendered by each [`Mode`](crate::mode::Mode) in its own
+/// syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// An entry of the 256-color palette, written as its index (e.g. `136`).
+    Indexed(u8),
+    /// A 24-bit color, written as `#rrggbb`.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// The SGR parameters that select this color as the foreground, e.g. `32`,
+    /// `38;5;136` or `38;2;181;137;0`.
+    #[must_use]
+    pub fn sgr(self) -> String {
+        match self {
+            Self::Black => "30".to_owned(),
+            Self::Red => "31".to_owned(),
+            Self::Green => "32".to_owned(),
+            Self::Yellow => "33".to_owned(),
+            Self::Blue => "34".to_owned(),
+            Self::Magenta => "35".to_owned(),
+            Self::Cyan => "36".to_owned(),
+            Self::White => "37".to_owned(),
+            Self::Indexed(index) => format!("38;5;{index}"),
+            Self::Rgb(r, g, b) => format!("38;2;{r};{g};{b}"),
+        }
+    }
+}
+
+/// The form accepted by [`FromStr`], which is also what zsh's `%F{...}` takes.
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Black => f.write_str("black"),
+            Self::Red => f.write_str("red"),
+            Self::Green => f.write_str("green"),
+            Self::Yellow => f.write_str("yellow"),
+            Self::Blue => f.write_str("blue"),
+            Self::Magenta => f.write_str("magenta"),
+            Self::Cyan => f.write_str("cyan"),
+            Self::White => f.write_str("white"),
+            Self::Indexed(index) => write!(f, "{index}"),
+            Self::Rgb(r, g, b) => write!(f, "#{r:02x}{g:02x}{b:02x}"),
+        }
+    }
+}
+
+/// The error returned when parsing an unknown color.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown color `{0}`")]
+pub struct ParseColorError(String);
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || ParseColorError(s.to_owned());
+        Ok(match s {
+            "black" => Self::Black,
+            "red" => Self::Red,
+            "green" => Self::Green,
+            "yellow" => Self::Yellow,
+            "blue" => Self::Blue,
+            "magenta" => Self::Magenta,
+            "cyan" => Self::Cyan,
+            "white" => Self::White,
+            _ => {
+                if let Some(hex) = s.strip_prefix('#') {
+                    let channel = |i: usize| {
+                        hex.get(i..i + 2)
+                            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    };
+                    let valid = hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit());
+                    match (valid, channel(0), channel(2), channel(4)) {
+                        (true, Some(r), Some(g), Some(b)) => Self::Rgb(r, g, b),
+                        _ => return Err(unknown()),
+                    }
+                } else if s.bytes().all(|b| b.is_ascii_digit()) {
+                    Self::Indexed(s.parse().map_err(|_| unknown())?)
+                } else {
+                    return Err(unknown());
+                }
+            }
+        })
+    }
+}
+
+/// The color of each [`Status`], and of the in-progress action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub not_changed: Color,
+    pub staged: Color,
+    pub untracked: Color,
+    pub unstaged: Color,
+    pub conflicted: Color,
+    pub unknown: Color,
+    pub failed: Color,
+    /// The color of the action and its progress (e.g. `:rebase-i 3/12`), or
+    /// `None` to show them in the status color along with the name.
+    pub action: Option<Color>,
+    /// Escape sequences that replace the color of a status, see
+    /// [`Self::with_fish_colors`].
+    pub sequences: Vec<(Status, String)>,
+}
+
+impl Palette {
+    /// The built-in colors.
+    pub const DEFAULT: Self = Self {
+        not_changed: Color::Green,
+        staged: Color::Yellow,
+        untracked: Color::Cyan,
+        unstaged: Color::Red,
+        conflicted: Color::Red,
+        unknown: Color::Blue,
+        failed: Color::Magenta,
+        action: None,
+        sequences: Vec::new(),
+    };
+
+    /// The color `status` is shown in.
+    #[must_use]
+    pub const fn status(&self, status: &Status) -> Color {
+        match status {
+            Status::NotChanged => self.not_changed,
+            Status::Staged => self.staged,
+            Status::Untracked => self.untracked,
+            Status::Unstaged => self.unstaged,
+            Status::Conflicted => self.conflicted,
+            Status::Unknown => self.unknown,
+            Status::Failed => self.failed,
+        }
+    }
+
+    /// The escape sequence that replaces the color of `status`, if any.
+    #[must_use]
+    pub fn sequence(&self, status: &Status) -> Option<&str> {
+        self.sequences
+            .iter()
+            .find(|(other, _)| other == status)
+            .map(|(_, sequence)| sequence.as_str())
+    }
+
+    /// The palette with the status colors replaced by the sequences that
+    /// `init fish` exports as `__git_branch_status_color_<status>`: the output
+    /// of `set_color $git_branch_status_color_<status>` for every variable the
+    /// user has set. `var` looks up an environment variable.
+    #[must_use]
+    pub fn with_fish_colors(mut self, var: impl Fn(&str) -> Option<String>) -> Self {
+        self.sequences = [
+            Status::NotChanged,
+            Status::Staged,
+            Status::Untracked,
+            Status::Unstaged,
+            Status::Conflicted,
+            Status::Unknown,
+            Status::Failed,
+        ]
+        .into_iter()
+        .filter_map(|status| {
+            let sequence = var(&format!("__git_branch_status_color_{}", status.name()))?;
+            (!sequence.is_empty()).then_some((status, sequence))
+        })
+        .collect();
+        self
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The error returned when parsing a palette.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParsePaletteError {
+    #[error("expected `<key>=<color>`, found `{0}`")]
+    MissingColor(String),
+    #[error("unknown palette key `{0}`")]
+    UnknownKey(String),
+    #[error(transparent)]
+    Color(#[from] ParseColorError),
+}
+
+/// Parses comma-separated `<key>=<color>` pairs on top of the built-in colors,
+/// e.g. `staged=136,unstaged=#dc322f,action=magenta`. The keys are the
+/// [`Status`] names and `action`.
+impl FromStr for Palette {
+    type Err = ParsePaletteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut palette = Self::DEFAULT;
+        for entry in s.split(',').filter(|entry| !entry.is_empty()) {
+            let (key, color) = entry
+                .split_once('=')
+                .ok_or_else(|| ParsePaletteError::MissingColor(entry.to_owned()))?;
+            let color = color.parse()?;
+            let slot = match key {
+                "not_changed" => &mut palette.not_changed,
+                "staged" => &mut palette.staged,
+                "untracked" => &mut palette.untracked,
+                "unstaged" => &mut palette.unstaged,
+                "conflicted" => &mut palette.conflicted,
+                "unknown" => &mut palette.unknown,
+                "failed" => &mut palette.failed,
+                "action" => {
+                    palette.action = Some(color);
+                    continue;
+                }
+                _ => return Err(ParsePaletteError::UnknownKey(key.to_owned())),
+            };
+            *slot = color;
+        }
+        Ok(palette)
+    }
+}
+
+/// When to color the output, as chosen with `--color`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Follow `NO_COLOR`, `CLICOLOR_FORCE` and git's `color.ui`, and otherwise
+    /// color only output that is displayed.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color the output.
+    ///
+    /// For `auto`, a non-empty `NO_COLOR` wins over a `CLICOLOR_FORCE` other
+    /// than `0`, which wins over `color_ui`, the value of git's `color.ui`.
+    /// Like git's colorbool, `color_ui` is case-insensitive and may also be a
+    /// boolean: false values such as `off` or `0` mean `never`, and true values
+    /// mean `auto`, which falls back to `displayed`: whether the output ends
+    /// up on a screen, as prompt output always does.
+    #[must_use]
+    pub fn enabled(
+        self,
+        var: impl Fn(&str) -> Option<OsString>,
+        color_ui: Option<&str>,
+        displayed: bool,
+    ) -> bool {
+        let set = |key| var(key).filter(|value| !value.is_empty());
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto if set("NO_COLOR").is_some() => false,
+            Self::Auto if set("CLICOLOR_FORCE").is_some_and(|value| value != "0") => true,
+            Self::Auto => match color_ui.map(str::to_ascii_lowercase).as_deref() {
+                Some("always") => true,
+                Some("never") => false,
+                Some("auto") | None => displayed,
+                Some(value) => Boolean::try_from(BStr::new(value))
+                    .map_or(displayed, |enabled| enabled.0 && displayed),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str_round_trips_name() {
+        for color in [
+            Color::Black,
+            Color::Red,
+            Color::Green,
+            Color::Yellow,
+            Color::Blue,
+            Color::Magenta,
+            Color::Cyan,
+            Color::White,
+        ] {
+            assert_eq!(color.to_string().parse(), Ok(color));
+        }
+    }
+
+    #[test]
+    fn test_from_str_round_trips_indexed_and_rgb() {
+        for color in [
+            Color::Indexed(0),
+            Color::Indexed(255),
+            Color::Rgb(0xb5, 0x89, 0x00),
+        ] {
+            assert_eq!(color.to_string().parse(), Ok(color));
+        }
+    }
+
+    #[test]
+    fn test_from_str_parses_indexed_and_rgb() {
+        assert_eq!("136".parse(), Ok(Color::Indexed(136)));
+        assert_eq!("#DC322F".parse(), Ok(Color::Rgb(0xdc, 0x32, 0x2f)));
+    }
+
+    #[test]
+    fn test_from_str_rejects_malformed_indexed_and_rgb() {
+        for s in ["256", "", "#12345", "#1234567", "#gggggg", "+1", "#+1+2+3"] {
+            assert_eq!(
+                s.parse::<Color>(),
+                Err(ParseColorError(s.to_owned())),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sgr() {
+        assert_eq!(Color::Green.sgr(), "32");
+        assert_eq!(Color::Indexed(136).sgr(), "38;5;136");
+        assert_eq!(Color::Rgb(1, 2, 3).sgr(), "38;2;1;2;3");
+    }
+
+    #[test]
+    fn test_palette_overrides_defaults() -> Result<(), ParsePaletteError> {
+        let palette: Palette = "staged=136,unstaged=#dc322f,action=magenta".parse()?;
+        assert_eq!(
+            palette,
+            Palette {
+                staged: Color::Indexed(136),
+                unstaged: Color::Rgb(0xdc, 0x32, 0x2f),
+                action: Some(Color::Magenta),
+                ..Palette::DEFAULT
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_palette_with_fish_colors() {
+        let palette = Palette::DEFAULT.with_fish_colors(|key| match key {
+            "__git_branch_status_color_staged" => Some("\x1b[1m".to_owned()),
+            "__git_branch_status_color_unstaged" => Some(String::new()),
+            _ => None,
+        });
+        assert_eq!(palette.sequence(&Status::Staged), Some("\x1b[1m"));
+        assert_eq!(palette.sequence(&Status::Unstaged), None);
+        assert_eq!(palette.sequence(&Status::NotChanged), None);
+    }
+
+    #[test]
+    fn test_palette_empty_is_default() {
+        assert_eq!("".parse(), Ok(Palette::DEFAULT));
+    }
+
+    #[test]
+    fn test_palette_rejects_unknown_key() {
+        assert_eq!(
+            "branch=red".parse::<Palette>(),
+            Err(ParsePaletteError::UnknownKey("branch".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_palette_rejects_missing_color() {
+        assert_eq!(
+            "staged".parse::<Palette>(),
+            Err(ParsePaletteError::MissingColor("staged".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_palette_rejects_unknown_color() {
+        assert_eq!(
+            "staged=purple".parse::<Palette>(),
+            Err(ParsePaletteError::Color(ParseColorError(
+                "purple".to_owned()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_from_str_rejects_unknown_name() {
+        assert_eq!(
+            "purple".parse::<Color>(),
+            Err(ParseColorError("purple".to_owned()))
+        );
+    }
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        |key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| OsString::from(value))
+        }
+    }
+
+    #[test]
+    fn test_color_choice_always_and_never_ignore_environment() {
+        let vars = [("NO_COLOR", "1")];
+        assert!(ColorChoice::Always.enabled(env(&vars), Some("never"), false));
+        assert!(!ColorChoice::Never.enabled(env(&[]), Some("always"), true));
+    }
+
+    #[test]
+    fn test_color_choice_auto_follows_displayed() {
+        assert!(ColorChoice::Auto.enabled(env(&[]), None, true));
+        assert!(!ColorChoice::Auto.enabled(env(&[]), None, false));
+        assert!(!ColorChoice::Auto.enabled(env(&[]), Some("true"), false));
+    }
+
+    #[test]
+    fn test_color_choice_auto_honors_no_color() {
+        let vars = [("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")];
+        assert!(!ColorChoice::Auto.enabled(env(&vars), Some("always"), true));
+    }
+
+    #[test]
+    fn test_color_choice_auto_ignores_empty_no_color() {
+        let vars = [("NO_COLOR", "")];
+        assert!(ColorChoice::Auto.enabled(env(&vars), None, true));
+    }
+
+    #[test]
+    fn test_color_choice_auto_honors_clicolor_force() {
+        assert!(ColorChoice::Auto.enabled(env(&[("CLICOLOR_FORCE", "1")]), Some("never"), false));
+        assert!(!ColorChoice::Auto.enabled(env(&[("CLICOLOR_FORCE", "0")]), None, false));
+    }
+
+    #[test]
+    fn test_color_choice_auto_honors_color_ui() {
+        assert!(ColorChoice::Auto.enabled(env(&[]), Some("always"), false));
+        assert!(!ColorChoice::Auto.enabled(env(&[]), Some("never"), true));
+        assert!(!ColorChoice::Auto.enabled(env(&[]), Some("false"), true));
+    }
+
+    #[test]
+    fn test_color_choice_auto_parses_color_ui_like_git() {
+        for value in ["Never", "FALSE", "no", "Off", "0", ""] {
+            assert!(
+                !ColorChoice::Auto.enabled(env(&[]), Some(value), true),
+                "{value}"
+            );
+        }
+        assert!(ColorChoice::Auto.enabled(env(&[]), Some("Always"), false));
+        for value in ["AUTO", "true", "Yes", "on", "1"] {
+            assert!(
+                ColorChoice::Auto.enabled(env(&[]), Some(value), true),
+                "{value}"
+            );
+            assert!(
+                !ColorChoice::Auto.enabled(env(&[]), Some(value), false),
+                "{value}"
+            );
+        }
+    }
+}
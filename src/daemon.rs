@@ -0,0 +1,652 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A long-running server that keeps repositories open and answers queries over
+//! a Unix socket, so prompts in huge worktrees do not pay for a scan each time.
+//!
+//! Every worktree asked about is watched recursively (inotify on Linux). A
+//! change bumps the worktree's generation, which marks the cached reports as
+//! stale, and they are recomputed in the background once the changes settle.
+//! Changes to untracked files that are ignored (e.g. a build in `target/`)
+//! are skipped. The recomputation is a full scan of the worktree, not just of
+//! the paths that changed, so the daemon saves the prompt from waiting for a
+//! scan rather than saving the scan itself.
+//! A change to a config file also reopens the repository, whose config is
+//! otherwise only read once.
+//!
+//! The protocol is one JSON [`Request`] per connection, written as a single
+//! line, answered with a single-line JSON [`Response`].
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead as _, BufReader, Write as _};
+use std::os::unix::fs::{
+    DirBuilderExt as _, FileTypeExt as _, MetadataExt as _, PermissionsExt as _,
+};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use rustix::process::getuid;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::report::Report;
+use crate::repository::Repository;
+
+/// How long a burst of changes (e.g. a checkout) must be quiet before the
+/// cached reports are recomputed.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// A query for the [`Report`] of the repository containing `dir`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    /// An absolute path inside the repository.
+    pub dir: PathBuf,
+    #[serde(flatten)]
+    pub options: Options,
+}
+
+/// What to compute, as set by `--untracked-files` and `--counts`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Options {
+    pub untracked_files: bool,
+    pub counts: bool,
+}
+
+/// The answer to a [`Request`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Report(Box<Report>),
+    /// The directory is not in a repository, or its branch cannot be read.
+    Error(String),
+}
+
+/// The socket used when `--socket` is not given.
+///
+/// That is `$XDG_RUNTIME_DIR/git-branch-status.sock`, or else `daemon.sock`
+/// in a directory of the temporary directory named after the user ID, which
+/// only the user may enter. `var` looks up an environment variable.
+pub fn default_socket(var: impl Fn(&str) -> Option<OsString>) -> PathBuf {
+    var("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(
+            || {
+                let dir = format!("git-branch-status-{}", getuid().as_raw());
+                env::temp_dir().join(dir).join("daemon.sock")
+            },
+            |dir| PathBuf::from(dir).join("git-branch-status.sock"),
+        )
+}
+
+/// Ask the daemon listening on `socket`, waiting at most `timeout` for the
+/// answer.
+///
+/// # Errors
+///
+/// Returns an error if no daemon is listening on `socket`, the socket
+/// belongs to another user, or the daemon does not answer in time.
+pub fn query(socket: &Path, request: &Request, timeout: Option<Duration>) -> io::Result<Response> {
+    // Another user's daemon could read the request and make up the answer.
+    if !owned(&fs::metadata(socket)?) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} belongs to another user", socket.display()),
+        ));
+    }
+    let mut stream = UnixStream::connect(socket)?;
+    // A zero timeout is rejected, and would mean waiting forever anyway.
+    let timeout = timeout.map(|timeout| timeout.max(Duration::from_millis(1)));
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    write_line(&mut stream, request)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(Into::into)
+}
+
+/// Listen on `socket` and answer queries until the process is killed.
+///
+/// # Errors
+///
+/// Returns an error if another daemon is already listening on `socket`, or
+/// it cannot be bound.
+pub fn serve(socket: &Path) -> io::Result<()> {
+    let listener = bind(socket)?;
+    let daemon = Arc::new(Daemon::default());
+    for stream in listener.incoming().flatten() {
+        let daemon = Arc::clone(&daemon);
+        // A client that went away is no concern of the others.
+        thread::spawn(move || daemon.handle(stream).ok());
+    }
+    Ok(())
+}
+
+/// Bind `socket`, replacing a stale socket left behind by a daemon that was
+/// killed.
+///
+/// A missing parent directory is created so that only the user may enter
+/// it. An existing one must belong to the user or to root, so that no other
+/// user can swap the socket.
+fn bind(socket: &Path) -> io::Result<UnixListener> {
+    if let Some(parent) = socket
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)?;
+        let metadata = fs::metadata(parent)?;
+        if !owned(&metadata) && metadata.uid() != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} belongs to another user", parent.display()),
+            ));
+        }
+    }
+    if UnixStream::connect(socket).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("a daemon is already listening on {}", socket.display()),
+        ));
+    }
+    let stale = fs::symlink_metadata(socket).is_ok_and(|metadata| metadata.file_type().is_socket());
+    if stale {
+        fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    // Reports reveal branch names and file counts, so only the owner may ask.
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Whether the file belongs to the user running this process.
+fn owned(metadata: &fs::Metadata) -> bool {
+    metadata.uid() == getuid().as_raw()
+}
+
+fn write_line(stream: &mut UnixStream, value: &impl Serialize) -> io::Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    stream.write_all(&line)
+}
+
+/// The worktrees opened so far, by every directory that was asked about.
+#[derive(Default)]
+struct Daemon {
+    worktrees: Mutex<HashMap<PathBuf, Arc<Worktree>>>,
+}
+
+impl Daemon {
+    fn handle(&self, mut stream: UnixStream) -> io::Result<()> {
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => self.respond(&request),
+            Err(err) => Response::Error(err.to_string()),
+        };
+        write_line(&mut stream, &response)
+    }
+
+    fn respond(&self, request: &Request) -> Response {
+        let result = self.worktree(&request.dir).and_then(|worktree| {
+            worktree
+                .report(request.options)
+                .inspect_err(|_| self.forget(&worktree))
+        });
+        match result {
+            Ok(report) => Response::Report(Box::new(report)),
+            Err(err) => Response::Error(err.to_string()),
+        }
+    }
+
+    /// The worktree containing `dir`, opening and watching it on first use.
+    ///
+    /// Discovering and watching a huge worktree takes a while, so it happens
+    /// without holding the lock, which would keep queries for every other
+    /// worktree waiting.
+    fn worktree(&self, dir: &Path) -> Result<Arc<Worktree>, Error> {
+        if let Some(worktree) = self.lock().get(dir) {
+            return Ok(Arc::clone(worktree));
+        }
+        let repo = Repository::discover(dir)?;
+        let root = repo.paths().into_iter().next().unwrap_or_default();
+        // Another directory of the same worktree may have been asked about.
+        let opened = self
+            .find(&root)
+            .unwrap_or_else(|| Worktree::open(repo, root));
+        // Another query may have opened the worktree meanwhile, in which case
+        // that one is kept and the one opened here is dropped with its watcher.
+        let mut worktrees = self.lock();
+        let worktree = worktrees
+            .values()
+            .find(|worktree| worktree.root == opened.root)
+            .map_or(opened, Arc::clone);
+        worktrees.insert(dir.to_path_buf(), Arc::clone(&worktree));
+        drop(worktrees);
+        Ok(worktree)
+    }
+
+    /// The worktree rooted at `root`, if it was opened before.
+    fn find(&self, root: &Path) -> Option<Arc<Worktree>> {
+        self.lock()
+            .values()
+            .find(|worktree| worktree.root == root)
+            .map(Arc::clone)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<PathBuf, Arc<Worktree>>> {
+        self.worktrees
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Drop `worktree`, e.g. because it was deleted, so the next query for it
+    /// starts afresh.
+    fn forget(&self, worktree: &Arc<Worktree>) {
+        self.lock().retain(|_, other| !Arc::ptr_eq(other, worktree));
+    }
+}
+
+/// An open repository and the reports computed for it.
+struct Worktree {
+    /// The first of [`Repository::paths`], identifying the worktree.
+    root: PathBuf,
+    cache: Mutex<Cache>,
+    /// Bumped on every change the watcher reports, so a report computed at an
+    /// older generation is known to be stale.
+    generation: Arc<AtomicU64>,
+    /// Set when a config file changed, since `gix` only reads the config when
+    /// the repository is opened.
+    config_changed: Arc<AtomicBool>,
+    /// `None` if the worktree could not be watched (e.g. because the inotify
+    /// watch limit was reached), in which case nothing is cached.
+    watcher: Option<RecommendedWatcher>,
+}
+
+struct Cache {
+    repo: Repository,
+    /// The latest report for each set of options asked for, and the
+    /// generation it was computed at.
+    reports: HashMap<Options, (u64, Report)>,
+}
+
+impl Worktree {
+    fn open(repo: Repository, root: PathBuf) -> Arc<Self> {
+        let generation = Arc::new(AtomicU64::new(0));
+        let config_changed = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let bump = Arc::clone(&generation);
+        let reload = Arc::clone(&config_changed);
+        let paths = repo.paths();
+        let mut ignores = repo.clone();
+        let workdir = root.clone();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            // Reading files is no change, and neither is writing ignored ones
+            // (e.g. a build in `target/`), but a dropped event might have been.
+            let (changed, config) = event.map_or((true, true), |event| {
+                let config = event.paths.iter().any(|path| is_config(path));
+                let changed = !matches!(event.kind, EventKind::Access(_))
+                    && (config
+                        || event.paths.is_empty()
+                        || !event.paths.iter().all(|path| ignores.is_ignored(path)));
+                (changed, config)
+            });
+            if changed {
+                if config {
+                    reload.store(true, Ordering::SeqCst);
+                    // The config may name another excludes file.
+                    if let Ok(repo) = Repository::discover(&workdir) {
+                        ignores = repo;
+                    }
+                }
+                bump.fetch_add(1, Ordering::SeqCst);
+                sender.send(()).ok();
+            }
+        })
+        .and_then(|mut watcher| {
+            for path in &paths {
+                watcher.watch(path, RecursiveMode::Recursive)?;
+            }
+            Ok(watcher)
+        })
+        .ok();
+        let worktree = Arc::new(Self {
+            root,
+            cache: Mutex::new(Cache {
+                repo,
+                reports: HashMap::new(),
+            }),
+            generation,
+            config_changed,
+            watcher,
+        });
+
+        // The thread ends once the worktree, and with it the sender in the
+        // watcher, is dropped.
+        let weak = Arc::downgrade(&worktree);
+        thread::spawn(move || {
+            while receiver.recv().is_ok() {
+                while receiver.recv_timeout(DEBOUNCE).is_ok() {}
+                let Some(worktree) = weak.upgrade() else {
+                    break;
+                };
+                worktree.refresh();
+            }
+        });
+        worktree
+    }
+
+    /// The report for `options`, computed now unless an up-to-date one is
+    /// cached.
+    fn report(&self, options: Options) -> Result<Report, Error> {
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        // Read before computing, so a change during the scan leaves the result
+        // stale.
+        let generation = self.generation.load(Ordering::SeqCst);
+        let cached = cache
+            .reports
+            .get(&options)
+            .filter(|(at, _)| *at == generation);
+        if let Some((_, report)) = cached {
+            return Ok(report.clone());
+        }
+        self.reload(&mut cache);
+        let report = cache.collect(options)?;
+        if self.watcher.is_some() {
+            cache.reports.insert(options, (generation, report.clone()));
+        }
+        drop(cache);
+        Ok(report)
+    }
+
+    /// Recompute every stale report that was asked for before.
+    fn refresh(&self) {
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let generation = self.generation.load(Ordering::SeqCst);
+        let stale: Vec<Options> = cache
+            .reports
+            .iter()
+            .filter(|(_, (at, _))| *at != generation)
+            .map(|(options, _)| *options)
+            .collect();
+        self.reload(&mut cache);
+        for options in stale {
+            match cache.collect(options) {
+                Ok(report) => cache.reports.insert(options, (generation, report)),
+                Err(_) => cache.reports.remove(&options),
+            };
+        }
+    }
+    /// Reopen the repository if its config may have changed since it was
+    /// opened, which is always the case when the worktree is not watched.
+    fn reload(&self, cache: &mut Cache) {
+        let changed = self.watcher.is_none() || self.config_changed.swap(false, Ordering::SeqCst);
+        if changed {
+            if let Ok(repo) = Repository::discover(&self.root) {
+                cache.repo = repo;
+            }
+        }
+    }
+}
+
+/// Whether `path` may be a git config file. A tracked file named `config`
+/// merely causes a needless reload.
+fn is_config(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name == "config" || name == "config.worktree")
+}
+
+impl Cache {
+    fn collect(&self, options: Options) -> Result<Report, Error> {
+        let repo = self.repo.clone().untracked_files(options.untracked_files);
+        Report::collect(&repo, options.counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use anyhow::{Result, bail};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    use super::*;
+    use crate::branch::Status;
+    use crate::test_util::git;
+
+    /// Serve on a socket in a fresh directory from a background thread, which
+    /// lives until the test process exits.
+    fn spawn_daemon() -> Result<(TempDir, PathBuf)> {
+        let dir = TempDir::new()?;
+        let socket = dir.path().join("daemon.sock");
+        let listener = bind(&socket)?;
+        thread::spawn(move || {
+            let daemon = Arc::new(Daemon::default());
+            for stream in listener.incoming().flatten() {
+                daemon.handle(stream).ok();
+            }
+        });
+        Ok((dir, socket))
+    }
+
+    fn report(socket: &Path, dir: &Path) -> Result<Report> {
+        let request = Request {
+            dir: dir.to_path_buf(),
+            options: Options::default(),
+        };
+        match query(socket, &request, None)? {
+            Response::Report(report) => Ok(*report),
+            Response::Error(message) => bail!(message),
+        }
+    }
+
+    fn status(socket: &Path, dir: &Path) -> Result<Status> {
+        Ok(report(socket, dir)?.branch.status)
+    }
+
+    /// Poll the daemon until the report for `dir` satisfies `done`.
+    fn wait_for(socket: &Path, dir: &Path, done: impl Fn(&Report) -> bool) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !done(&report(socket, dir)?) {
+            if Instant::now() > deadline {
+                bail!("the change was not picked up");
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_socket_in_runtime_dir() {
+        let socket =
+            default_socket(|key| (key == "XDG_RUNTIME_DIR").then(|| "/run/user/1000".into()));
+        assert_eq!(socket, Path::new("/run/user/1000/git-branch-status.sock"));
+    }
+
+    #[test]
+    fn test_default_socket_in_temp_dir() {
+        let socket = default_socket(|_| None);
+        let dir = format!("git-branch-status-{}", getuid().as_raw());
+        assert_eq!(socket, env::temp_dir().join(dir).join("daemon.sock"));
+    }
+
+    #[test]
+    fn test_request_is_a_flat_json_object() -> Result<()> {
+        let request = Request {
+            dir: PathBuf::from("/repo"),
+            options: Options {
+                untracked_files: false,
+                counts: true,
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&request)?,
+            r#"{"dir":"/repo","untracked_files":false,"counts":true}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bind_refuses_a_socket_in_use() -> Result<()> {
+        let (dir, socket) = spawn_daemon()?;
+        let err = bind(&socket).err().map(|err| err.kind());
+        assert_eq!(err, Some(io::ErrorKind::AddrInUse));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn bind_replaces_a_stale_socket() -> Result<()> {
+        let dir = TempDir::new()?;
+        let socket = dir.path().join("daemon.sock");
+        drop(UnixListener::bind(&socket)?);
+        assert!(bind(&socket).is_ok());
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn bind_creates_a_private_directory() -> Result<()> {
+        let dir = TempDir::new()?;
+        let socket = dir.path().join("private").join("daemon.sock");
+        bind(&socket)?;
+        let mode = fs::metadata(dir.path().join("private"))?
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn bind_keeps_a_file_that_is_not_a_socket() -> Result<()> {
+        let dir = TempDir::new()?;
+        let file = dir.child("file");
+        file.write_str("keep")?;
+        assert!(bind(file.path()).is_err());
+        file.assert("keep");
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn query_answers_with_an_error_outside_a_repository() -> Result<()> {
+        let (dir, socket) = spawn_daemon()?;
+        let request = Request {
+            dir: dir.path().to_path_buf(),
+            options: Options::default(),
+        };
+        let response = query(&socket, &request, None)?;
+        assert!(matches!(response, Response::Error(_)));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn query_follows_changes_to_the_worktree() -> Result<()> {
+        let (dir, socket) = spawn_daemon()?;
+        let repo = TempDir::new()?;
+        git(repo.path(), &["init", "-q", "-b", "main"]);
+        repo.child("f").write_str("a\n")?;
+        git(repo.path(), &["add", "f"]);
+        assert_eq!(status(&socket, repo.path())?, Status::Staged);
+
+        repo.child("f").write_str("b\n")?;
+        wait_for(&socket, repo.path(), |report| {
+            report.branch.status == Status::Unstaged
+        })?;
+        repo.close()?;
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn query_follows_changes_to_the_config() -> Result<()> {
+        let (dir, socket) = spawn_daemon()?;
+        let repo = TempDir::new()?;
+        git(repo.path(), &["init", "-q", "-b", "main"]);
+        assert_eq!(report(&socket, repo.path())?.color_ui, None);
+
+        git(repo.path(), &["config", "color.ui", "never"]);
+        wait_for(&socket, repo.path(), |report| {
+            report.color_ui.as_deref() == Some("never")
+        })?;
+        repo.close()?;
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn query_skips_changes_to_ignored_files() -> Result<()> {
+        let repo = TempDir::new()?;
+        git(repo.path(), &["init", "-q", "-b", "main"]);
+        repo.child(".gitignore").write_str("target/\n")?;
+        git(repo.path(), &["add", ".gitignore"]);
+        let daemon = Daemon::default();
+        let worktree = daemon.worktree(repo.path())?;
+        // Let the events of opening the repository settle.
+        thread::sleep(Duration::from_millis(200));
+        let generation = worktree.generation.load(Ordering::SeqCst);
+
+        repo.child("target/debug/out").write_str("a\n")?;
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(worktree.generation.load(Ordering::SeqCst), generation);
+
+        repo.child("f").write_str("a\n")?;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while worktree.generation.load(Ordering::SeqCst) == generation {
+            if Instant::now() > deadline {
+                bail!("the change was not picked up");
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        drop(worktree);
+        drop(daemon);
+        repo.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn worktree_is_opened_once_for_concurrent_queries() -> Result<()> {
+        let repo = TempDir::new()?;
+        git(repo.path(), &["init", "-q", "-b", "main"]);
+        repo.child("sub").create_dir_all()?;
+        let daemon = Daemon::default();
+        let sub = repo.path().join("sub");
+        let worktrees = thread::scope(|scope| {
+            let mut threads = Vec::new();
+            for dir in [repo.path(), &sub].repeat(4) {
+                threads.push(scope.spawn(|| daemon.worktree(dir)));
+            }
+            threads
+                .into_iter()
+                .map(|thread| match thread.join() {
+                    Ok(worktree) => worktree.map_err(Into::into),
+                    Err(_) => bail!("a query panicked"),
+                })
+                .collect::<Result<Vec<_>>>()
+        })?;
+        assert!(
+            worktrees
+                .iter()
+                .all(|worktree| Arc::ptr_eq(worktree, &worktrees[0]))
+        );
+        drop(worktrees);
+        drop(daemon);
+        repo.close().map_err(Into::into)
+    }
+}
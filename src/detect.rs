@@ -0,0 +1,234 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of the calling shell for `--mode auto`.
+//!
+//! The parent process is the most reliable signal, since prompts run this tool
+//! from a `$(...)` subshell of the shell that draws them. Shell-specific
+//! environment variables come next, then the login shell in `$SHELL`, and
+//! `PSModulePath` last, since Windows sets it for every process.
+
+use std::env;
+use std::ffi::OsString;
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::io::{self, IsTerminal as _};
+#[cfg(target_os = "linux")]
+use std::os::unix::process::parent_id;
+use std::path::Path;
+
+use crate::mode::Mode;
+
+/// Environment variables that identify the shell that exported them. Most
+/// shells do not export these by default, but integration snippets may.
+const SHELL_VARIABLES: [(&str, Mode); 4] = [
+    ("ZSH_VERSION", Mode::Zsh),
+    ("BASH_VERSION", Mode::Bash),
+    ("FISH_VERSION", Mode::Fish),
+    ("NU_VERSION", Mode::Nushell),
+];
+
+/// Processes between the shell and this one that are looked through: `git`
+/// running `git branch-status`, and `sh -c` as used by tmux for `#(...)` and
+/// by git for `!` aliases.
+const WRAPPERS: [&str; 3] = ["git", "sh", "dash"];
+
+/// The mode for the shell this process was started from, or
+/// [`Mode::Stdout`] when the shell is not recognized.
+///
+/// [`Mode::Stdout`] is also used when stdout is not a terminal, so piped or
+/// redirected output never carries prompt escapes.
+#[must_use]
+pub fn mode() -> Mode {
+    if !io::stdout().is_terminal() {
+        return Mode::Stdout;
+    }
+    parent_mode()
+        .or_else(|| env_mode(|key| env::var_os(key)))
+        .unwrap_or(Mode::Stdout)
+}
+
+/// The mode for the parent process, looking through [`WRAPPERS`].
+#[cfg(target_os = "linux")]
+fn parent_mode() -> Option<Mode> {
+    ancestor_mode(parent_id(), process_name, parent_of)
+}
+
+/// The mode for the process `pid`, or for its closest ancestor that is not
+/// one of at most two [`WRAPPERS`], e.g. for `bash` → `git` → `sh`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn ancestor_mode(
+    mut pid: u32,
+    name_of: impl Fn(u32) -> Option<String>,
+    parent_of: impl Fn(u32) -> Option<u32>,
+) -> Option<Mode> {
+    for _ in 0..2 {
+        let name = name_of(pid)?;
+        if !WRAPPERS.contains(&name.as_str()) {
+            return shell_mode(&name);
+        }
+        pid = parent_of(pid)?;
+    }
+    shell_mode(&name_of(pid)?)
+}
+
+/// The parent process cannot be inspected cheaply on this platform.
+#[cfg(not(target_os = "linux"))]
+const fn parent_mode() -> Option<Mode> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn process_name(pid: u32) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    Some(comm.trim_end().to_owned())
+}
+
+#[cfg(target_os = "linux")]
+fn parent_of(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    parse_parent(&stat)
+}
+
+/// The parent PID from the contents of `/proc/<pid>/stat`. The command name in
+/// the second field may itself contain spaces and parentheses, so fields are
+/// counted from the last `)`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_parent(stat: &str) -> Option<u32> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// The mode from shell-specific variables, then from the login shell in
+/// `$SHELL`, and finally from `PSModulePath`.
+fn env_mode(var: impl Fn(&str) -> Option<OsString>) -> Option<Mode> {
+    SHELL_VARIABLES
+        .into_iter()
+        .find(|(key, _)| var(key).is_some())
+        .map(|(_, mode)| mode)
+        .or_else(|| {
+            let shell = var("SHELL")?;
+            let name = Path::new(&shell).file_name()?.to_str()?.to_owned();
+            shell_mode(&name)
+        })
+        .or_else(|| var("PSModulePath").map(|_| Mode::PowerShell))
+}
+
+/// The mode for a shell's process or executable name, e.g. `zsh`, `-bash`
+/// (a login shell) or `pwsh.exe`.
+fn shell_mode(name: &str) -> Option<Mode> {
+    let name = name.trim_start_matches('-');
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+    match name {
+        "zsh" => Some(Mode::Zsh),
+        "bash" => Some(Mode::Bash),
+        "tcsh" | "csh" => Some(Mode::Tcsh),
+        "fish" => Some(Mode::Fish),
+        "pwsh" | "powershell" => Some(Mode::PowerShell),
+        "nu" => Some(Mode::Nushell),
+        _ if name.starts_with("tmux") => Some(Mode::Tmux),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_mode_recognizes_shells() {
+        assert_eq!(shell_mode("zsh"), Some(Mode::Zsh));
+        assert_eq!(shell_mode("-bash"), Some(Mode::Bash));
+        assert_eq!(shell_mode("tcsh"), Some(Mode::Tcsh));
+        assert_eq!(shell_mode("fish"), Some(Mode::Fish));
+        assert_eq!(shell_mode("pwsh.exe"), Some(Mode::PowerShell));
+        assert_eq!(shell_mode("nu"), Some(Mode::Nushell));
+        assert_eq!(shell_mode("tmux: server"), Some(Mode::Tmux));
+    }
+
+    #[test]
+    fn test_shell_mode_rejects_other_processes() {
+        assert_eq!(shell_mode("sh"), None);
+        assert_eq!(shell_mode("cargo"), None);
+    }
+
+    #[test]
+    fn test_env_mode_prefers_shell_variables_over_login_shell() {
+        let var = |key: &str| match key {
+            "FISH_VERSION" => Some(OsString::from("3.7.1")),
+            "SHELL" => Some(OsString::from("/bin/zsh")),
+            _ => None,
+        };
+        assert_eq!(env_mode(var), Some(Mode::Fish));
+    }
+
+    #[test]
+    fn test_env_mode_falls_back_to_login_shell() {
+        let var = |key: &str| (key == "SHELL").then(|| OsString::from("/usr/local/bin/bash"));
+        assert_eq!(env_mode(var), Some(Mode::Bash));
+    }
+
+    #[test]
+    fn test_env_mode_checks_ps_module_path_last() {
+        let var = |key: &str| match key {
+            "PSModulePath" => Some(OsString::from(r"C:\Modules")),
+            "SHELL" => Some(OsString::from("/usr/bin/bash")),
+            _ => None,
+        };
+        assert_eq!(env_mode(var), Some(Mode::Bash));
+        let var = |key: &str| (key == "PSModulePath").then(|| OsString::from(r"C:\Modules"));
+        assert_eq!(env_mode(var), Some(Mode::PowerShell));
+    }
+
+    #[test]
+    fn test_env_mode_without_hints() {
+        assert_eq!(env_mode(|_| None), None);
+    }
+
+    /// The mode for process 1 in a process tree given as the names of
+    /// process 1, its parent 2, and so on.
+    fn ancestor_mode_of(names: &[&str]) -> Option<Mode> {
+        let index = |pid: u32| usize::try_from(pid).ok()?.checked_sub(1);
+        ancestor_mode(
+            1,
+            |pid| names.get(index(pid)?).map(|name| (*name).to_owned()),
+            |pid| Some(pid + 1),
+        )
+    }
+
+    #[test]
+    fn test_ancestor_mode_of_a_shell() {
+        assert_eq!(ancestor_mode_of(&["bash"]), Some(Mode::Bash));
+    }
+
+    #[test]
+    fn test_ancestor_mode_looks_through_git_and_sh() {
+        assert_eq!(ancestor_mode_of(&["git", "bash"]), Some(Mode::Bash));
+        assert_eq!(ancestor_mode_of(&["sh", "tmux: server"]), Some(Mode::Tmux));
+        assert_eq!(ancestor_mode_of(&["sh", "git", "zsh"]), Some(Mode::Zsh));
+        assert_eq!(ancestor_mode_of(&["git", "sh", "fish"]), Some(Mode::Fish));
+    }
+
+    #[test]
+    fn test_ancestor_mode_stops_after_two_wrappers() {
+        assert_eq!(ancestor_mode_of(&["git", "sh", "git", "bash"]), None);
+        assert_eq!(ancestor_mode_of(&["cargo", "bash"]), None);
+    }
+
+    #[test]
+    fn test_parse_parent_with_parentheses_in_name() {
+        let stat = "4242 (weird) name) S 1234 4242 4242 0 -1";
+        assert_eq!(parse_parent(stat), Some(1234));
+    }
+}
@@ -17,18 +17,30 @@
 /// The single domain error for the `gix` backend.
 ///
 /// The many per-operation `gix` error types (some of which are large) are boxed
-/// behind this one type, so the rest of the crate never names a `gix` type and
+/// behind one variant, so the rest of the crate never names a `gix` type and
 /// the `Result` stays small.
 #[derive(Debug, thiserror::Error)]
-#[error(transparent)]
-pub struct Error(Box<dyn std::error::Error + Send + Sync + 'static>);
+pub enum Error {
+    /// An error from one of the `gix` operations.
+    #[error(transparent)]
+    Git(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    /// An I/O error outside of `gix`, e.g. when talking to the daemon.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Git could report a rename or copy, which the porcelain output does not
+    /// support.
+    #[error("rename detection is not supported; set status.renames to false")]
+    Renames,
+}
 
 macro_rules! impl_from_gix_error {
     ($($ty:ty),+ $(,)?) => {
         $(
             impl From<$ty> for Error {
                 fn from(err: $ty) -> Self {
-                    Error(Box::new(err))
+                    Self::Git(Box::new(err))
                 }
             }
         )+
@@ -37,7 +49,13 @@ macro_rules! impl_from_gix_error {
 
 impl_from_gix_error!(
     gix::discover::Error,
+    gix::reference::find::Error,
     gix::reference::find::existing::Error,
+    gix::reference::peel::Error,
+    gix::repository::branch_remote_ref_name::Error,
+    gix::repository::branch_remote_tracking_ref_name::Error,
+    gix::revision::walk::Error,
+    gix::revision::walk::iter::Error,
     gix::status::Error,
     gix::status::into_iter::Error,
     gix::status::iter::Error,
@@ -58,4 +76,12 @@ mod tests {
             },
         ));
     }
+
+    #[test]
+    fn test_from_io_error() {
+        let err = Error::from(std::io::Error::other("daemon went away"));
+
+        assert!(matches!(err, Error::Io(_)));
+        assert_eq!(err.to_string(), "daemon went away");
+    }
 }
@@ -0,0 +1,442 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A server speaking the wire protocol of `gitstatusd`, the daemon behind
+//! gitstatus and powerlevel10k, so `GITSTATUS_DAEMON` can point at this tool.
+//!
+//! Requests are read from stdin and responses written to stdout. Both are
+//! records terminated by ASCII 30 (record separator), made of fields separated
+//! by ASCII 31 (unit separator). A request holds an id, the directory to look
+//! at, prefixed with `:` if it is a git directory, and optionally `1` to skip
+//! everything that reads the index. A response repeats the id, then `0` if
+//! the directory is not in a repository, or `1` followed by the 27 fields
+//! written by [`respond`].
+
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use clap::Args;
+
+use crate::branch::HeadKind;
+use crate::repository::Repository;
+
+/// Terminates requests and responses.
+const RECORD_SEPARATOR: u8 = 0x1e;
+
+/// Separates the fields of requests and responses.
+const UNIT_SEPARATOR: &str = "\x1f";
+
+/// The options `gitstatusd` is started with. The limits are honored; the
+/// other options only make sense for `gitstatusd` itself and are ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+#[non_exhaustive]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Options {
+    /// Reports at most this many staged files; -1 for no limit
+    #[arg(short = 's', long, default_value_t = 1, allow_negative_numbers = true)]
+    pub max_num_staged: i64,
+
+    /// Reports at most this many unstaged files; -1 for no limit
+    #[arg(short = 'u', long, default_value_t = 1, allow_negative_numbers = true)]
+    pub max_num_unstaged: i64,
+
+    /// Reports at most this many conflicted files; -1 for no limit
+    #[arg(short = 'c', long, default_value_t = 1, allow_negative_numbers = true)]
+    pub max_num_conflicted: i64,
+
+    /// Reports at most this many untracked files; -1 for no limit, 0 to not
+    /// look for them
+    #[arg(short = 'd', long, default_value_t = 1, allow_negative_numbers = true)]
+    pub max_num_untracked: i64,
+
+    /// Skips the worktree scan when the index holds more files than this; -1
+    /// for no limit
+    #[arg(short = 'm', long, default_value_t = -1, allow_negative_numbers = true)]
+    pub dirty_max_index_size: i64,
+
+    #[arg(short = 'l', long, hide = true)]
+    pub lock_fd: Option<String>,
+
+    #[arg(short = 'p', long, hide = true)]
+    pub parent_pid: Option<String>,
+
+    #[arg(short = 't', long, hide = true)]
+    pub num_threads: Option<String>,
+
+    #[arg(short = 'v', long, hide = true)]
+    pub log_level: Option<String>,
+
+    #[arg(short = 'r', long, hide = true)]
+    pub repo_ttl_seconds: Option<String>,
+
+    #[arg(short = 'z', long, hide = true)]
+    pub max_commit_summary_length: Option<String>,
+
+    #[arg(short = 'G', long, hide = true)]
+    pub version_glob: Option<String>,
+
+    #[arg(short = 'e', long, hide = true)]
+    pub recurse_untracked_dirs: bool,
+
+    #[arg(short = 'U', long, hide = true)]
+    pub ignore_status_show_untracked_files: bool,
+
+    #[arg(short = 'W', long, hide = true)]
+    pub ignore_bash_show_untracked_files: bool,
+
+    #[arg(short = 'D', long, hide = true)]
+    pub ignore_bash_show_dirty_state: bool,
+}
+
+/// Answer every request read from `input` on `output`, in order, until
+/// `input` ends.
+///
+/// # Errors
+///
+/// Returns an error if reading `input` or writing `output` fails.
+pub fn serve(input: impl BufRead, mut output: impl Write, options: &Options) -> io::Result<()> {
+    for record in input.split(RECORD_SEPARATOR) {
+        let record = String::from_utf8_lossy(&record?).into_owned();
+        let mut response = respond(&record, options);
+        response.push(char::from(RECORD_SEPARATOR));
+        output.write_all(response.as_bytes())?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// The response to one request, without the record separator.
+///
+/// After the id and `1`, the fields are: the worktree, the HEAD commit, the
+/// local branch, the upstream branch, its remote and the remote's URL, the
+/// action, the number of index entries, the staged, unstaged, conflicted and
+/// untracked files, the commits ahead of and behind the upstream, the stashes,
+/// a tag at HEAD and the unstaged deletions. Staged additions and deletions,
+/// the push remote, skip-worktree and assume-unchanged entries, and the commit
+/// message are not tracked, and always reported as zero or empty.
+#[must_use]
+pub fn respond(record: &str, options: &Options) -> String {
+    let mut fields = record.split(UNIT_SEPARATOR);
+    let id = fields.next().unwrap_or_default();
+    let dir = fields.next().unwrap_or_default();
+    let read_index = fields.next() != Some("1");
+    let dir = dir.strip_prefix(':').unwrap_or(dir);
+    let stats = if Path::new(dir).is_absolute() {
+        stats(Path::new(dir), read_index, options)
+    } else {
+        None
+    };
+    let mut response = vec![id.to_owned()];
+    match stats {
+        Some(stats) => {
+            response.push("1".to_owned());
+            response.extend(stats);
+        }
+        None => response.push("0".to_owned()),
+    }
+    response.join(UNIT_SEPARATOR)
+}
+
+/// The fields after `1` for the repository containing `dir`, or `None` if
+/// there is none or it has no worktree.
+fn stats(dir: &Path, read_index: bool, options: &Options) -> Option<Vec<String>> {
+    let repo = Repository::discover(dir)
+        .ok()?
+        .untracked_files(options.max_num_untracked != 0);
+    let workdir = repo.workdir()?.to_string_lossy().into_owned();
+    let detached = repo.head_kind().ok()? == HeadKind::Detached;
+    let branch = if detached {
+        String::new()
+    } else {
+        repo.branch().ok()?
+    };
+    let remote = repo.upstream_remote().ok().flatten();
+    let upstream = repo.upstream().ok().flatten();
+    let index_len = if read_index {
+        repo.index_len().ok()
+    } else {
+        None
+    };
+    let max_index_len = options.dirty_max_index_size;
+    let scan = index_len.is_some_and(|len| {
+        max_index_len < 0 || i64::try_from(len).is_ok_and(|len| len <= max_index_len)
+    });
+    let changes = if scan {
+        repo.changes().ok().flatten()
+    } else {
+        None
+    }
+    .unwrap_or_default();
+    let count = |count: usize, max: i64| {
+        usize::try_from(max)
+            .map_or(count, |max| count.min(max))
+            .to_string()
+    };
+
+    Some(vec![
+        workdir,
+        repo.head_id().unwrap_or_default(),
+        branch,
+        remote
+            .as_ref()
+            .map(|remote| remote.branch.clone())
+            .unwrap_or_default(),
+        remote
+            .as_ref()
+            .map(|remote| remote.name.clone())
+            .unwrap_or_default(),
+        remote.and_then(|remote| remote.url).unwrap_or_default(),
+        repo.action().unwrap_or_default(),
+        index_len.unwrap_or_default().to_string(),
+        count(changes.staged, options.max_num_staged),
+        count(changes.unstaged + changes.deleted, options.max_num_unstaged),
+        count(changes.conflicted, options.max_num_conflicted),
+        count(changes.untracked, options.max_num_untracked),
+        upstream
+            .as_ref()
+            .map_or(0, |upstream| upstream.ahead)
+            .to_string(),
+        upstream
+            .as_ref()
+            .map_or(0, |upstream| upstream.behind)
+            .to_string(),
+        repo.stash_count().unwrap_or_default().to_string(),
+        repo.tag_name().unwrap_or_default(),
+        count(changes.deleted, options.max_num_unstaged),
+        "0".to_owned(),
+        "0".to_owned(),
+        String::new(),
+        String::new(),
+        "0".to_owned(),
+        "0".to_owned(),
+        "0".to_owned(),
+        "0".to_owned(),
+        String::new(),
+        String::new(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::iter;
+
+    use anyhow::Result;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+    use clap::Parser;
+
+    use super::*;
+    use crate::test_util::git;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        options: Options,
+    }
+
+    fn options(args: &[&str]) -> Options {
+        Cli::parse_from(iter::once("gitstatusd").chain(args.iter().copied())).options
+    }
+
+    /// A repository on `main` tracking `origin/main` one commit behind, with
+    /// two staged files, an unstaged change, an unstaged deletion and two
+    /// untracked files.
+    fn fixture() -> Result<TempDir> {
+        let dir = TempDir::new()?;
+        git(dir.path(), &["init", "-q", "-b", "main"]);
+        dir.child("a").write_str("a\n")?;
+        dir.child("b").write_str("b\n")?;
+        git(dir.path(), &["add", "a", "b"]);
+        git(dir.path(), &["commit", "-qm", "init"]);
+        git(
+            dir.path(),
+            &["remote", "add", "origin", "https://example.com/repo.git"],
+        );
+        git(dir.path(), &["config", "branch.main.remote", "origin"]);
+        git(
+            dir.path(),
+            &["config", "branch.main.merge", "refs/heads/main"],
+        );
+        git(
+            dir.path(),
+            &["update-ref", "refs/remotes/origin/main", "HEAD"],
+        );
+        git(
+            dir.path(),
+            &["commit", "-q", "--allow-empty", "-m", "ahead"],
+        );
+        git(dir.path(), &["tag", "v1"]);
+        dir.child("c").write_str("c\n")?;
+        dir.child("d").write_str("d\n")?;
+        git(dir.path(), &["add", "c", "d"]);
+        dir.child("a").write_str("changed\n")?;
+        fs::remove_file(dir.child("b").path())?;
+        dir.child("x").write_str("x\n")?;
+        dir.child("y").write_str("y\n")?;
+        Ok(dir)
+    }
+
+    fn fields(response: &str) -> Vec<&str> {
+        response.split(UNIT_SEPARATOR).collect()
+    }
+
+    #[test]
+    fn test_respond_to_hello() {
+        // The handshake gitstatus sends right after starting the daemon.
+        assert_eq!(respond("}hello\x1f", &options(&[])), "}hello\x1f0");
+    }
+
+    #[test]
+    fn test_respond_to_relative_dir() {
+        assert_eq!(respond("id\x1fsome/dir", &options(&[])), "id\x1f0");
+    }
+
+    #[test]
+    fn test_options_accept_gitstatusd_arguments() {
+        let options = options(&[
+            "--parent-pid=1",
+            "--num-threads=8",
+            "--max-num-staged=-1",
+            "-u",
+            "10",
+            "--dirty-max-index-size=-1",
+            "--recurse-untracked-dirs",
+            "-U",
+            "-W",
+            "-D",
+        ]);
+        assert_eq!(options.max_num_staged, -1);
+        assert_eq!(options.max_num_unstaged, 10);
+        assert_eq!(options.max_num_conflicted, 1);
+    }
+
+    #[test]
+    fn respond_outside_repository() -> Result<()> {
+        let dir = TempDir::new()?;
+        let request = format!("id\x1f{}", dir.path().display());
+        assert_eq!(respond(&request, &options(&[])), "id\x1f0");
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn respond_with_all_fields() -> Result<()> {
+        let dir = fixture()?;
+        let request = format!("id\x1f{}", dir.path().display());
+        let response = respond(
+            &request,
+            &options(&["-s", "-1", "-u", "-1", "-c", "-1", "-d", "-1"]),
+        );
+        let fields = fields(&response);
+        assert_eq!(fields.len(), 29);
+        assert_eq!(fields[..3], ["id", "1", &dir.path().display().to_string()]);
+        assert_eq!(fields[3].len(), 40);
+        assert_eq!(
+            fields[4..],
+            [
+                "main",
+                "main",
+                "origin",
+                "https://example.com/repo.git",
+                "",
+                "4",
+                "2",
+                "2",
+                "0",
+                "2",
+                "1",
+                "0",
+                "0",
+                "v1",
+                "1",
+                "0",
+                "0",
+                "",
+                "",
+                "0",
+                "0",
+                "0",
+                "0",
+                "",
+                "",
+            ]
+        );
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn respond_caps_counts() -> Result<()> {
+        let dir = fixture()?;
+        let request = format!("id\x1f{}", dir.path().display());
+        let response = respond(&request, &options(&[]));
+        assert_eq!(fields(&response)[10..14], ["1", "1", "0", "1"]);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn respond_without_untracked_files() -> Result<()> {
+        let dir = fixture()?;
+        let request = format!("id\x1f{}", dir.path().display());
+        let response = respond(&request, &options(&["-d", "0"]));
+        assert_eq!(fields(&response)[13], "0");
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn respond_without_reading_index() -> Result<()> {
+        let dir = fixture()?;
+        let request = format!("id\x1f{}\x1f1", dir.path().display());
+        let response = respond(&request, &options(&["-s", "-1"]));
+        assert_eq!(fields(&response)[9..14], ["0", "0", "0", "0", "0"]);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn respond_skips_scan_of_large_index() -> Result<()> {
+        let dir = fixture()?;
+        let request = format!("id\x1f{}", dir.path().display());
+        let response = respond(&request, &options(&["-s", "-1", "-m", "3"]));
+        assert_eq!(fields(&response)[9..11], ["4", "0"]);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn respond_to_git_dir() -> Result<()> {
+        let dir = fixture()?;
+        let request = format!("id\x1f:{}", dir.path().join(".git").display());
+        let response = respond(&request, &options(&[]));
+        assert_eq!(fields(&response)[..2], ["id", "1"]);
+        assert_eq!(fields(&response)[4], "main");
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn respond_with_empty_branch_when_detached() -> Result<()> {
+        let dir = fixture()?;
+        git(dir.path(), &["checkout", "-q", "--detach"]);
+        let request = format!("id\x1f{}", dir.path().display());
+        let response = respond(&request, &options(&[]));
+        assert_eq!(fields(&response)[4..6], ["", ""]);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn serve_answers_every_record() -> Result<()> {
+        let input = "a\x1frel\x1e}hello\x1f\x1e";
+        let mut output = Vec::new();
+        serve(input.as_bytes(), &mut output, &options(&[]))?;
+        assert_eq!(String::from_utf8(output)?, "a\x1f0\x1e}hello\x1f0\x1e");
+        Ok(())
+    }
+}
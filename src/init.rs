@@ -0,0 +1,108 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shell integration scripts printed by `git branch-status init <shell>`.
+//!
+//! The scripts live in `src/shell/` and are embedded at build time. Each one
+//! installs a prompt hook that skips directories outside git repositories
+//! without starting a process, and calls the binary in the matching [`Mode`].
+//!
+//! [`Mode`]: crate::mode::Mode
+
+use clap::ValueEnum;
+
+/// A shell that `init` can print an integration script for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Zsh,
+    Bash,
+    Fish,
+    #[value(name = "powershell", alias = "pwsh")]
+    PowerShell,
+}
+
+impl Shell {
+    /// The integration script, to be evaluated by the shell's configuration.
+    /// Both zsh scripts get the repository check from `shell/in-repo.zsh`.
+    #[must_use]
+    pub const fn script(self) -> &'static str {
+        match self {
+            Self::Zsh => concat!(
+                include_str!("shell/init.zsh"),
+                "\n",
+                include_str!("shell/in-repo.zsh"),
+            ),
+            Self::Bash => include_str!("shell/init.bash"),
+            Self::Fish => include_str!("shell/init.fish"),
+            Self::PowerShell => include_str!("shell/init.ps1"),
+        }
+    }
+
+    /// The integration script that runs the binary in the background and
+    /// updates the prompt when it finishes, for shells that support it.
+    #[must_use]
+    pub const fn async_script(self) -> Option<&'static str> {
+        match self {
+            Self::Zsh => Some(concat!(
+                include_str!("shell/init-async.zsh"),
+                "\n",
+                include_str!("shell/in-repo.zsh"),
+            )),
+            Self::Bash | Self::Fish | Self::PowerShell => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_call_the_matching_mode() {
+        for (shell, mode) in [
+            (Shell::Zsh, "--mode zsh"),
+            (Shell::Bash, "--mode bash"),
+            (Shell::Fish, "--mode fish"),
+            (Shell::PowerShell, "--mode powershell"),
+        ] {
+            assert!(shell.script().contains(mode), "{shell:?}");
+        }
+    }
+
+    #[test]
+    fn test_async_script_is_only_available_for_zsh() {
+        let script = Shell::Zsh.async_script().unwrap_or_default();
+        assert!(script.contains("zle -F"));
+        assert!(script.contains("--mode zsh"));
+        assert_eq!(Shell::Bash.async_script(), None);
+    }
+
+    #[test]
+    fn test_zsh_scripts_define_the_repository_check() {
+        let scripts = [
+            Shell::Zsh.script(),
+            Shell::Zsh.async_script().unwrap_or_default(),
+        ];
+        for script in scripts {
+            assert!(script.contains("__git_branch_status_in_repo() {"));
+        }
+    }
+
+    #[test]
+    fn test_scripts_check_for_a_repository_first() {
+        for shell in Shell::value_variants() {
+            assert!(shell.script().contains(".git"), "{shell:?}");
+        }
+    }
+}
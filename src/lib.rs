@@ -14,6 +14,19 @@
 
 pub mod branch;
 pub mod cli;
+pub mod color;
+#[cfg(unix)]
+pub mod daemon;
+pub mod detect;
 pub mod error;
+pub mod gitstatusd;
+pub mod init;
 pub mod mode;
+pub mod porcelain;
+pub mod ps1;
+pub mod report;
 pub mod repository;
+pub mod template;
+#[cfg(test)]
+mod test_util;
+pub mod vcs_info;
@@ -12,35 +12,245 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::process::exit;
+use std::env;
+use std::io::{self, IsTerminal as _, Write as _};
+#[cfg(unix)]
+use std::path;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory as _, Parser};
 
-use git_branch_status::branch::Branch;
-use git_branch_status::cli::Cli;
+use git_branch_status::cli::{Cli, Command};
+#[cfg(unix)]
+use git_branch_status::daemon::{self, Options, Request, Response};
 use git_branch_status::error::Error;
+use git_branch_status::gitstatusd;
+use git_branch_status::mode::Mode;
+use git_branch_status::porcelain;
+use git_branch_status::ps1;
+use git_branch_status::report::Report;
 use git_branch_status::repository::Repository;
+use git_branch_status::vcs_info;
 
-fn run(cli: Cli) -> Result<String, Error> {
-    let repo = Repository::discover(cli.dir)?;
-    let branch = Branch {
-        name: repo.branch_name()?,
-        status: repo.branch_status()?,
+/// A flag raised by a background thread once `timeout` has elapsed.
+///
+/// The thread is never joined: if the run finishes first, it is torn down when
+/// the process exits.
+fn deadline(timeout: Duration) -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(false));
+    let raise = Arc::clone(&flag);
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        raise.store(true, Ordering::SeqCst);
+    });
+    flag
+}
+
+/// How long to wait for the daemon when no `--timeout` is given, before
+/// scanning the worktree directly instead, so a hung daemon cannot block the
+/// prompt.
+#[cfg(unix)]
+const DAEMON_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// The formatted output, and whether every part of it could be determined.
+struct Output {
+    text: String,
+    complete: bool,
+}
+
+/// The report from the daemon listening on `--socket`, or `None` if there is
+/// none or it does not answer in time, in which case the worktree is scanned
+/// directly.
+#[cfg(unix)]
+fn query_daemon(cli: &Cli, untracked_files: bool, counts: bool) -> Option<Result<Report, Error>> {
+    let request = Request {
+        dir: path::absolute(&cli.dir).ok()?,
+        options: Options {
+            untracked_files,
+            counts,
+        },
     };
-    let output = cli.mode.format(&branch);
+    let socket = cli
+        .socket
+        .clone()
+        .unwrap_or_else(|| daemon::default_socket(|key| env::var_os(key)));
+    let timeout = cli.timeout.map_or(DAEMON_TIMEOUT, Duration::from_millis);
+    match daemon::query(&socket, &request, Some(timeout)).ok()? {
+        Response::Report(report) => Some(Ok(*report)),
+        Response::Error(message) => Some(Err(io::Error::other(message).into())),
+    }
+}
 
-    Ok(output)
+#[cfg(not(unix))]
+const fn query_daemon(
+    _cli: &Cli,
+    _untracked_files: bool,
+    _counts: bool,
+) -> Option<Result<Report, Error>> {
+    None
 }
 
-fn main() {
-    let cli = Cli::parse();
+fn scan(
+    cli: &Cli,
+    untracked_files: bool,
+    counts: bool,
+    deadline: Option<Arc<AtomicBool>>,
+) -> Result<Report, Error> {
+    let mut repo = Repository::discover(&cli.dir)?.untracked_files(untracked_files);
+    if let Some(flag) = deadline {
+        repo = repo.should_interrupt(flag);
+    }
+    Report::collect(&repo, counts)
+}
+
+/// The report from the daemon, or else from scanning the worktree.
+fn report(cli: &Cli, untracked_files: bool, counts: bool) -> Result<Report, Error> {
+    // Start the clock before asking the daemon or opening the repository, so
+    // the timeout bounds the whole run as closely as the interruptible scan
+    // allows.
+    let deadline = cli.timeout.map(|ms| deadline(Duration::from_millis(ms)));
+    query_daemon(cli, untracked_files, counts)
+        .unwrap_or_else(|| scan(cli, untracked_files, counts, deadline))
+}
+
+/// Whether the changes are counted, for `--counts` or a `vcs_info` format
+/// that shows staged or unstaged changes.
+fn counts(cli: &Cli) -> bool {
+    cli.counts || cli.vcs_info.needs_counts()
+}
 
-    match run(cli) {
-        Ok(output) => print!("{output}"),
-        // Intentionally silent: any output on stderr would appear in the
-        // terminal when this tool is invoked inside a shell prompt via $().
-        // A non-zero exit code is the only signal we can emit without
-        // polluting the prompt.
-        Err(_) => exit(1),
+fn run(cli: &Cli, mode: &Mode) -> Result<Output, Error> {
+    let report = report(cli, cli.untracked_files, counts(cli))?;
+    // A `vcs_info` format brings its own colors as prompt sequences.
+    if cli.vcs_info.enabled() {
+        return Ok(Output {
+            text: vcs_info::format(mode, &report, &cli.dir, &cli.vcs_info),
+            complete: report.complete,
+        });
+    }
+    let Report {
+        branch,
+        complete,
+        color_ui,
+        ..
+    } = report;
+    // Prompt output is captured rather than written to a terminal, but it is
+    // still displayed by the shell.
+    let displayed = *mode != Mode::Stdout || io::stdout().is_terminal();
+    let colored = cli
+        .color
+        .enabled(|key| env::var_os(key), color_ui.as_deref(), displayed);
+    let palette = colored.then(|| {
+        let palette = cli.colors.clone().unwrap_or_default();
+        if *mode == Mode::Fish {
+            palette.with_fish_colors(|key| env::var(key).ok())
+        } else {
+            palette
+        }
+    });
+    let text = cli.format.as_ref().map_or_else(
+        || mode.format(&branch, palette.as_ref()),
+        |template| mode.format_template(&branch, template, palette.as_ref()),
+    );
+
+    Ok(Output { text, complete })
+}
+
+/// The branch like `__git_ps1` shows it, put into `format` in place of `%s`.
+fn run_ps1(cli: &Cli, format: &str, mode: &Mode) -> Result<Output, Error> {
+    let options = ps1::Options::from_env(|key| env::var_os(key));
+    let Report {
+        branch,
+        complete,
+        head_name,
+        tag,
+        ..
+    } = report(cli, options.untracked_files, options.needs_counts())?;
+    let ps1 = ps1::format(&branch, &head_name, tag.as_deref(), &options);
+    // With `prompt_subst`, zsh expands `%` sequences in the output of `$(...)`
+    // in a prompt, such as the untracked marker.
+    let ps1 = match mode {
+        Mode::Zsh => mode.escape(&ps1).into_owned(),
+        _ => ps1,
+    };
+    let text = ps1::expand(format, &ps1);
+    Ok(Output { text, complete })
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    #[cfg(unix)]
+    if cli.command == Some(Command::Daemon) {
+        let socket = cli
+            .socket
+            .unwrap_or_else(|| daemon::default_socket(|key| env::var_os(key)));
+        if let Err(err) = daemon::serve(&socket) {
+            Cli::command().error(ErrorKind::Io, err).exit();
+        }
+        return ExitCode::SUCCESS;
+    }
+    if cli.command == Some(Command::Porcelain) {
+        return porcelain::Status::render(&cli.dir).map_or_else(
+            |err| {
+                // Unlike a prompt, a script should learn why it got nothing.
+                eprintln!("error: {err}");
+                ExitCode::FAILURE
+            },
+            |output| {
+                // A closed pipe is no reason to report a failure.
+                io::stdout().write_all(&output).ok();
+                ExitCode::SUCCESS
+            },
+        );
+    }
+    if let Some(Command::Gitstatusd(options)) = &cli.command {
+        let result = gitstatusd::serve(io::stdin().lock(), io::stdout().lock(), options);
+        // The client hanging up is how the session normally ends.
+        return if result.is_ok() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+    if let Some(Command::Init {
+        shell,
+        asynchronous,
+    }) = cli.command
+    {
+        let script = if asynchronous {
+            shell.async_script().unwrap_or_else(|| {
+                Cli::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "--async is only supported for zsh",
+                    )
+                    .exit()
+            })
+        } else {
+            shell.script()
+        };
+        print!("{script}");
+        return ExitCode::SUCCESS;
+    }
+    let mode = cli.mode.clone().resolve();
+    let result = match &cli.command {
+        Some(Command::Ps1 { format }) => run_ps1(&cli, format, &mode),
+        _ => run(&cli, &mode),
+    };
+    match result {
+        Ok(Output { text, complete }) => {
+            print!("{text}");
+            if complete {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(_) => ExitCode::FAILURE,
     }
 }
@@ -12,41 +12,329 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::borrow::Cow;
+
 use clap::ValueEnum;
-use owo_colors::OwoColorize as _;
+use owo_colors::{AnsiColors, DynColors, OwoColorize as _, XtermColors};
+use serde::Serialize;
 
 use crate::branch::{Branch, Status};
+use crate::color::{Color, Palette};
+use crate::template::Template;
 
 #[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
 pub enum Mode {
     Stdout,
     Zsh,
+    Bash,
+    Tcsh,
+    Fish,
+    Tmux,
+    #[value(name = "powershell", alias = "pwsh")]
+    PowerShell,
+    Nushell,
+    Json,
+    Nuon,
+}
+
+/// The version of the `--mode json` schema, emitted as its `version` field.
+///
+/// It is bumped whenever a field is removed or its meaning changes. Adding a
+/// field is not a breaking change, so consumers should ignore unknown fields.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// The `--mode json` document: the schema version followed by the fields of
+/// [`Branch`].
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    version: u32,
+    #[serde(flatten)]
+    branch: &'a Branch,
 }
 
 impl Mode {
-    fn format_stdout(branch: &Branch) -> String {
-        match branch.status {
-            Status::NotChanged => format!("{}", branch.name.green()),
-            Status::Staged => format!("{}", branch.name.yellow()),
-            Status::Unstaged | Status::Conflicted => format!("{}", branch.name.red()),
-        }
+    /// The branch label in its status color, or followed by the status marker
+    /// without a palette, and then the suffix. The action part of the label
+    /// gets its own color if the palette has one.
+    fn format_prompt(&self, branch: &Branch, palette: Option<&Palette>) -> String {
+        let suffix = self.escape(&Self::suffix(branch)).into_owned();
+        let Some(palette) = palette else {
+            let label = branch.label() + branch.status.marker();
+            return self.escape(&label).into_owned() + &suffix;
+        };
+        let label = palette
+            .action
+            .filter(|_| branch.action.is_some())
+            .map_or_else(
+                || {
+                    let label = branch.label();
+                    self.paint_status(palette, &branch.status, &self.escape(&label))
+                },
+                |action| {
+                    let action_label = branch.action_label();
+                    let name = self.escape(&branch.name);
+                    self.paint_status(palette, &branch.status, &name)
+                        + &self.paint(action, &self.escape(&action_label))
+                },
+            );
+        label + &suffix
     }
 
-    fn format_zsh(branch: &Branch) -> String {
-        let color = match branch.status {
-            Status::NotChanged => "green",
-            Status::Staged => "yellow",
-            Status::Unstaged | Status::Conflicted => "red",
+    /// A single-line JSON object describing the whole [`Branch`]. See the
+    /// README for the schema.
+    fn format_json(branch: &Branch) -> String {
+        let output = JsonOutput {
+            version: JSON_SCHEMA_VERSION,
+            branch,
         };
-        let name = branch.name.replace('%', "%%");
-        format!("%F{{{color}}}{name}%f")
+        // Serializing plain structs and enums with string keys cannot fail.
+        serde_json::to_string(&output).unwrap_or_default()
+    }
+
+    /// A NUON record with the branch name, the action (`null` without one) and
+    /// the status (e.g. `{branch: "main", action: null, status: "staged"}`),
+    /// which nushell parses with `from nuon`. With a `palette`, the status
+    /// color is added as a `color` field in a form nushell's `ansi` takes.
+    fn format_nuon(branch: &Branch, palette: Option<&Palette>) -> String {
+        let action = branch
+            .action
+            .as_deref()
+            .map_or_else(|| "null".to_owned(), nuon_string);
+        let color = palette.map_or_else(String::new, |palette| {
+            let color = nu_ansi(palette.status(&branch.status));
+            format!(", color: {}", nuon_string(&color))
+        });
+        format!(
+            "{{branch: {}, action: {action}, status: {}{color}}}",
+            nuon_string(&branch.name),
+            nuon_string(branch.status.name()),
+        )
     }
 
+    /// The change counts, the ahead/behind counts against the upstream and the
+    /// stash count (e.g. ` +3 ~2 !1 ↑1 ↓2 $3`), omitting any that is zero.
+    /// Unescaped.
+    fn suffix(branch: &Branch) -> String {
+        let changes = branch.changes.as_ref().map_or_else(Vec::new, |changes| {
+            [
+                ('+', changes.staged),
+                ('~', changes.unstaged),
+                ('-', changes.deleted),
+                ('!', changes.conflicted),
+                ('?', changes.untracked),
+            ]
+            .into_iter()
+            .filter(|&(_, count)| count > 0)
+            .map(|(marker, count)| format!(" {marker}{count}"))
+            .collect()
+        });
+        let upstream = branch.upstream.as_ref();
+        let ahead = upstream
+            .filter(|upstream| upstream.ahead > 0)
+            .map(|upstream| format!(" ↑{}", upstream.ahead));
+        let behind = upstream
+            .filter(|upstream| upstream.behind > 0)
+            .map(|upstream| format!(" ↓{}", upstream.behind));
+        let stash = (branch.stash > 0).then(|| format!(" ${}", branch.stash));
+        changes
+            .into_iter()
+            .chain(ahead)
+            .chain(behind)
+            .chain(stash)
+            .collect()
+    }
+
+    /// Escape `text` so it is displayed literally.
+    ///
+    /// Bash output is meant to be embedded in `PS1`, e.g. assigned from
+    /// `PROMPT_COMMAND` with `PS1="$(git branch-status --mode bash) \$ "`.
+    /// Since `PS1` is decoded and then expanded (`promptvars`) on every draw,
+    /// `\`, `$` and `` ` `` are escaped for both passes: prompt decoding turns
+    /// `\\` into `\`, after which expansion treats the remaining backslash as a
+    /// quote for the next character.
+    ///
+    /// Tmux output is meant for `#(...)` in `status-left`/`status-right`, whose
+    /// output is parsed for `#[...]` styles, so `#` is doubled.
+    ///
+    /// PowerShell output is returned from `prompt` as a value and never parsed
+    /// as code, so `` ` `` and `$` need no escaping.
+    ///
+    /// Tcsh output is meant for `prompt`, set from `precmd`, where `%` starts a
+    /// formatting sequence and `!` is replaced with the history number. Git
+    /// forbids `\` in ref names, so `\!` is unambiguous.
     #[must_use]
-    pub fn format(&self, branch: &Branch) -> String {
+    pub fn escape<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Stdout
+            | Self::Fish
+            | Self::PowerShell
+            | Self::Nushell
+            | Self::Json
+            | Self::Nuon => Cow::Borrowed(text),
+            Self::Zsh => Cow::Owned(text.replace('%', "%%")),
+            Self::Tmux => Cow::Owned(text.replace('#', "##")),
+            Self::Tcsh => Cow::Owned(text.replace('%', "%%").replace('!', r"\!")),
+            Self::Bash => Cow::Owned(
+                text.replace('\\', r"\\\\")
+                    .replace('$', r"\\$")
+                    .replace('`', r"\`"),
+            ),
+        }
+    }
+
+    /// Wrap already-escaped `text` in `color`.
+    ///
+    /// Bash escapes are wrapped in `\[ \]`, and tcsh escapes in `%{ %}`, so the
+    /// line editor excludes them from the line length. Fish, PowerShell and
+    /// nushell measure the prompt themselves, so plain SGR sequences are
+    /// emitted regardless of whether stdout is a terminal. PowerShell gets the
+    /// literal ESC character rather than `$([char]27)`, since the output is
+    /// data, not a script.
+    pub(crate) fn paint(&self, color: Color, text: &str) -> String {
         match self {
-            Self::Stdout => Self::format_stdout(branch),
-            Self::Zsh => Self::format_zsh(branch),
+            Self::Stdout => format!("{}", text.color(DynColors::from(color))),
+            Self::Zsh => format!("%F{{{color}}}{text}%f"),
+            Self::Bash => format!(r"\[\e[{}m\]{text}\[\e[39m\]", color.sgr()),
+            Self::Fish | Self::PowerShell | Self::Nushell => {
+                format!("\x1b[{}m{text}\x1b[39m", color.sgr())
+            }
+            Self::Tcsh => format!("%{{\x1b[{}m%}}{text}%{{\x1b[39m%}}", color.sgr()),
+            Self::Tmux => match color {
+                Color::Indexed(index) => format!("#[fg=colour{index}]{text}#[default]"),
+                _ => format!("#[fg={color}]{text}#[default]"),
+            },
+            Self::Json | Self::Nuon => text.to_owned(),
+        }
+    }
+
+    /// Wrap already-escaped `text` in the color of `status` in `palette`.
+    ///
+    /// An escape sequence in the palette, e.g. from fish's `set_color`, takes
+    /// the place of the color. It may also set attributes such as `--bold`,
+    /// hence the full reset afterwards.
+    pub(crate) fn paint_status(&self, palette: &Palette, status: &Status, text: &str) -> String {
+        palette.sequence(status).map_or_else(
+            || self.paint(palette.status(status), text),
+            |sequence| format!("{sequence}{text}\x1b[0m"),
+        )
+    }
+
+    /// Format `branch` with the built-in format, colored with `palette`.
+    /// Without one, the status is shown as a marker after the label instead
+    /// (see [`Status::marker`]).
+    #[must_use]
+    pub fn format(&self, branch: &Branch, palette: Option<&Palette>) -> String {
+        match self {
+            Self::Stdout
+            | Self::Zsh
+            | Self::Bash
+            | Self::Tcsh
+            | Self::Fish
+            | Self::Tmux
+            | Self::PowerShell
+            | Self::Nushell => self.format_prompt(branch, palette),
+            Self::Json => Self::format_json(branch),
+            Self::Nuon => Self::format_nuon(branch, palette),
+        }
+    }
+
+    /// Render `branch` with a user-defined `template` instead of the built-in
+    /// format. `--mode json` and `--mode nuon` always print their structured
+    /// output, since a template cannot produce it. Without a `palette`, color specs in
+    /// the template are ignored.
+    #[must_use]
+    pub fn format_template(
+        &self,
+        branch: &Branch,
+        template: &Template,
+        palette: Option<&Palette>,
+    ) -> String {
+        match self {
+            Self::Stdout
+            | Self::Zsh
+            | Self::Bash
+            | Self::Tcsh
+            | Self::Fish
+            | Self::Tmux
+            | Self::PowerShell
+            | Self::Nushell => template.render(self, branch, palette),
+            Self::Json => Self::format_json(branch),
+            Self::Nuon => Self::format_nuon(branch, palette),
+        }
+    }
+}
+
+/// `text` as a NUON string literal. Control characters are written as
+/// `\u{...}` escapes, the only form nushell accepts for arbitrary code points.
+fn nuon_string(text: &str) -> String {
+    let escaped: String = text
+        .chars()
+        .map(|c| match c {
+            '"' => r#"\""#.to_owned(),
+            '\\' => r"\\".to_owned(),
+            '\n' => r"\n".to_owned(),
+            '\r' => r"\r".to_owned(),
+            '\t' => r"\t".to_owned(),
+            c if c.is_control() => format!(r"\u{{{:x}}}", u32::from(c)),
+            c => c.to_string(),
+        })
+        .collect();
+    format!(r#""{escaped}""#)
+}
+
+/// `color` as an argument to nushell's `ansi` command: the name of a basic
+/// color, or `#rrggbb` otherwise. An indexed color is given the value of the
+/// xterm 256-color palette, since `ansi` has no names for most of them.
+fn nu_ansi(color: Color) -> String {
+    const SYSTEM: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00),
+        (0xcd, 0x00, 0x00),
+        (0x00, 0xcd, 0x00),
+        (0xcd, 0xcd, 0x00),
+        (0x00, 0x00, 0xee),
+        (0xcd, 0x00, 0xcd),
+        (0x00, 0xcd, 0xcd),
+        (0xe5, 0xe5, 0xe5),
+        (0x7f, 0x7f, 0x7f),
+        (0xff, 0x00, 0x00),
+        (0x00, 0xff, 0x00),
+        (0xff, 0xff, 0x00),
+        (0x5c, 0x5c, 0xff),
+        (0xff, 0x00, 0xff),
+        (0x00, 0xff, 0xff),
+        (0xff, 0xff, 0xff),
+    ];
+    const LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+    let (r, g, b) = match color {
+        Color::Indexed(index @ 0..=15) => SYSTEM[usize::from(index)],
+        Color::Indexed(index @ 16..=231) => {
+            let cube = index - 16;
+            let level = |n: u8| LEVELS[usize::from(n % 6)];
+            (level(cube / 36), level(cube / 6), level(cube))
+        }
+        Color::Indexed(index) => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+        Color::Rgb(r, g, b) => (r, g, b),
+        _ => return color.to_string(),
+    };
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+impl From<Color> for DynColors {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::Black => Self::Ansi(AnsiColors::Black),
+            Color::Red => Self::Ansi(AnsiColors::Red),
+            Color::Green => Self::Ansi(AnsiColors::Green),
+            Color::Yellow => Self::Ansi(AnsiColors::Yellow),
+            Color::Blue => Self::Ansi(AnsiColors::Blue),
+            Color::Magenta => Self::Ansi(AnsiColors::Magenta),
+            Color::Cyan => Self::Ansi(AnsiColors::Cyan),
+            Color::White => Self::Ansi(AnsiColors::White),
+            Color::Indexed(index) => Self::Xterm(XtermColors::from(index)),
+            Color::Rgb(r, g, b) => Self::Rgb(r, g, b),
         }
     }
 }
@@ -56,104 +344,1083 @@ mod tests {
     use owo_colors::OwoColorize as _;
 
     use super::*;
+    use crate::branch::{Changes, HeadKind, Status, Upstream};
+    use crate::template::ParseTemplateError;
+    use crate::test_util::branch;
+
+    const COLORED: Option<&Palette> = Some(&Palette::DEFAULT);
 
     #[test]
     fn test_stdout_not_changed() {
         let branch = Branch {
-            name: "main".to_owned(),
             status: Status::NotChanged,
+            ..branch("main")
         };
-        let actual = Mode::Stdout.format(&branch);
+        let actual = Mode::Stdout.format(&branch, COLORED);
         assert_eq!(actual, format!("{}", "main".green()));
     }
 
     #[test]
     fn test_stdout_staged() {
         let branch = Branch {
-            name: "main".to_owned(),
             status: Status::Staged,
+            ..branch("main")
         };
-        let actual = Mode::Stdout.format(&branch);
+        let actual = Mode::Stdout.format(&branch, COLORED);
         assert_eq!(actual, format!("{}", "main".yellow()));
     }
 
+    #[test]
+    fn test_stdout_untracked() {
+        let branch = Branch {
+            status: Status::Untracked,
+            ..branch("main")
+        };
+        let actual = Mode::Stdout.format(&branch, COLORED);
+        assert_eq!(actual, format!("{}", "main".cyan()));
+    }
+
     #[test]
     fn test_stdout_unstaged() {
         let branch = Branch {
-            name: "main".to_owned(),
             status: Status::Unstaged,
+            ..branch("main")
         };
-        let actual = Mode::Stdout.format(&branch);
+        let actual = Mode::Stdout.format(&branch, COLORED);
         assert_eq!(actual, format!("{}", "main".red()));
     }
 
     #[test]
     fn test_stdout_conflicted() {
         let branch = Branch {
-            name: "main".to_owned(),
             status: Status::Conflicted,
+            ..branch("main")
         };
-        let actual = Mode::Stdout.format(&branch);
+        let actual = Mode::Stdout.format(&branch, COLORED);
         assert_eq!(actual, format!("{}", "main".red()));
     }
 
+    #[test]
+    fn test_status_colors() {
+        for (status, name, sgr) in [
+            (Status::NotChanged, "green", 32),
+            (Status::Staged, "yellow", 33),
+            (Status::Untracked, "cyan", 36),
+            (Status::Unstaged, "red", 31),
+            (Status::Conflicted, "red", 31),
+            (Status::Unknown, "blue", 34),
+            (Status::Failed, "magenta", 35),
+        ] {
+            let branch = Branch {
+                status: status.clone(),
+                ..branch("main")
+            };
+            for (mode, expected) in [
+                (Mode::Stdout, format!("\x1b[{sgr}mmain\x1b[39m")),
+                (Mode::Zsh, format!("%F{{{name}}}main%f")),
+                (Mode::Bash, format!(r"\[\e[{sgr}m\]main\[\e[39m\]")),
+                (Mode::Tcsh, format!("%{{\x1b[{sgr}m%}}main%{{\x1b[39m%}}")),
+                (Mode::Tmux, format!("#[fg={name}]main#[default]")),
+                (Mode::Fish, format!("\x1b[{sgr}mmain\x1b[39m")),
+                (Mode::PowerShell, format!("\x1b[{sgr}mmain\x1b[39m")),
+                (Mode::Nushell, format!("\x1b[{sgr}mmain\x1b[39m")),
+            ] {
+                let actual = mode.format(&branch, COLORED);
+                assert_eq!(actual, expected, "{mode:?} {status:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_ahead_behind_follows_label() {
+        let branch = Branch {
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 1,
+                behind: 2,
+            }),
+            ..branch("main")
+        };
+        for (mode, expected) in [
+            (Mode::Zsh, "%F{green}main%f ↑1 ↓2"),
+            (Mode::Bash, r"\[\e[32m\]main\[\e[39m\] ↑1 ↓2"),
+            (Mode::Tmux, "#[fg=green]main#[default] ↑1 ↓2"),
+            (Mode::Fish, "\x1b[32mmain\x1b[39m ↑1 ↓2"),
+            (Mode::PowerShell, "\x1b[32mmain\x1b[39m ↑1 ↓2"),
+            (Mode::Nushell, "\x1b[32mmain\x1b[39m ↑1 ↓2"),
+        ] {
+            assert_eq!(mode.format(&branch, COLORED), expected, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_stdout_ahead_only() {
+        let branch = Branch {
+            status: Status::Staged,
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 3,
+                behind: 0,
+            }),
+            ..branch("main")
+        };
+        let actual = Mode::Stdout.format(&branch, COLORED);
+        assert_eq!(actual, format!("{} ↑3", "main".yellow()));
+    }
+
+    #[test]
+    fn test_stdout_unknown() {
+        let branch = Branch {
+            status: Status::Unknown,
+            ..branch("main")
+        };
+        let actual = Mode::Stdout.format(&branch, COLORED);
+        assert_eq!(actual, format!("{}", "main".blue()));
+    }
+
+    #[test]
+    fn test_stdout_failed() {
+        let branch = Branch {
+            status: Status::Failed,
+            ..branch("main")
+        };
+        let actual = Mode::Stdout.format(&branch, COLORED);
+        assert_eq!(actual, format!("{}", "main".magenta()));
+    }
+
     #[test]
     fn test_zsh_not_changed() {
         let branch = Branch {
-            name: "main".to_owned(),
             status: Status::NotChanged,
+            ..branch("main")
         };
-        let actual = Mode::Zsh.format(&branch);
+        let actual = Mode::Zsh.format(&branch, COLORED);
         assert_eq!(actual, "%F{green}main%f");
     }
 
     #[test]
     fn test_zsh_staged() {
         let branch = Branch {
-            name: "main".to_owned(),
             status: Status::Staged,
+            ..branch("main")
         };
-        let actual = Mode::Zsh.format(&branch);
+        let actual = Mode::Zsh.format(&branch, COLORED);
         assert_eq!(actual, "%F{yellow}main%f");
     }
 
+    #[test]
+    fn test_zsh_untracked() {
+        let branch = Branch {
+            status: Status::Untracked,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{cyan}main%f");
+    }
+
     #[test]
     fn test_zsh_unstaged() {
         let branch = Branch {
-            name: "main".to_owned(),
             status: Status::Unstaged,
+            ..branch("main")
         };
-        let actual = Mode::Zsh.format(&branch);
+        let actual = Mode::Zsh.format(&branch, COLORED);
         assert_eq!(actual, "%F{red}main%f");
     }
 
     #[test]
     fn test_zsh_conflicted() {
         let branch = Branch {
-            name: "main".to_owned(),
             status: Status::Conflicted,
+            ..branch("main")
         };
-        let actual = Mode::Zsh.format(&branch);
+        let actual = Mode::Zsh.format(&branch, COLORED);
         assert_eq!(actual, "%F{red}main%f");
     }
 
+    #[test]
+    fn test_zsh_unknown() {
+        let branch = Branch {
+            status: Status::Unknown,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{blue}main%f");
+    }
+
+    #[test]
+    fn test_zsh_failed() {
+        let branch = Branch {
+            status: Status::Failed,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{magenta}main%f");
+    }
+
+    #[test]
+    fn test_zsh_ahead_behind() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 1,
+                behind: 2,
+            }),
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{green}main%f ↑1 ↓2");
+    }
+
+    #[test]
+    fn test_zsh_in_sync_with_upstream() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 0,
+                behind: 0,
+            }),
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{green}main%f");
+    }
+
     #[test]
     fn test_zsh_escapes_percent_in_branch_name() {
         let branch = Branch {
-            name: "feature/%n".to_owned(),
             status: Status::NotChanged,
+            ..branch("feature/%n")
         };
-        let actual = Mode::Zsh.format(&branch);
+        let actual = Mode::Zsh.format(&branch, COLORED);
         assert_eq!(actual, "%F{green}feature/%%n%f");
     }
 
     #[test]
     fn test_zsh_escapes_trailing_percent_in_branch_name() {
         let branch = Branch {
-            name: "main%".to_owned(),
             status: Status::NotChanged,
+            ..branch("main%")
         };
-        let actual = Mode::Zsh.format(&branch);
+        let actual = Mode::Zsh.format(&branch, COLORED);
         assert_eq!(actual, "%F{green}main%%%f");
     }
+
+    #[test]
+    fn test_bash_not_changed() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[32m\]main\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_bash_staged() {
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[33m\]main\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_bash_untracked() {
+        let branch = Branch {
+            status: Status::Untracked,
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[36m\]main\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_bash_unstaged() {
+        let branch = Branch {
+            status: Status::Unstaged,
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[31m\]main\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_bash_conflicted() {
+        let branch = Branch {
+            status: Status::Conflicted,
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[31m\]main\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_bash_unknown() {
+        let branch = Branch {
+            status: Status::Unknown,
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[34m\]main\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_bash_failed() {
+        let branch = Branch {
+            status: Status::Failed,
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[35m\]main\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_bash_ahead_behind() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 1,
+                behind: 2,
+            }),
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[32m\]main\[\e[39m\] ↑1 ↓2");
+    }
+
+    #[test]
+    fn test_bash_escapes_backslash_in_branch_name() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch(r"feature\w")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[32m\]feature\\\\w\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_bash_escapes_dollar_in_branch_name() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("feature/$HOME")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[32m\]feature/\\$HOME\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_bash_escapes_backtick_in_branch_name() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("feature/`id`")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[32m\]feature/\`id\`\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_zsh_appends_action() {
+        let branch = Branch {
+            action: Some("rebase-i".to_owned()),
+            status: Status::Conflicted,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{red}main:rebase-i%f");
+    }
+
+    #[test]
+    fn test_json_not_changed() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("main")
+        };
+        let actual = Mode::Json.format(&branch, COLORED);
+        assert_eq!(
+            actual,
+            r#"{"version":1,"name":"main","action":null,"progress":null,"head":"symbolic","status":"not_changed","upstream":null,"stash":0,"changes":null}"#
+        );
+    }
+
+    #[test]
+    fn test_json_splits_action_from_name() {
+        let branch = Branch {
+            action: Some("rebase-i".to_owned()),
+            head: HeadKind::Detached,
+            status: Status::Conflicted,
+            ..branch("feature")
+        };
+        let actual = Mode::Json.format(&branch, COLORED);
+        assert_eq!(
+            actual,
+            r#"{"version":1,"name":"feature","action":"rebase-i","progress":null,"head":"detached","status":"conflicted","upstream":null,"stash":0,"changes":null}"#
+        );
+    }
+
+    #[test]
+    fn test_json_upstream() {
+        let branch = Branch {
+            head: HeadKind::Symbolic,
+            status: Status::Staged,
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 1,
+                behind: 2,
+            }),
+            ..branch("main")
+        };
+        let actual = Mode::Json.format(&branch, COLORED);
+        assert_eq!(
+            actual,
+            r#"{"version":1,"name":"main","action":null,"progress":null,"head":"symbolic","status":"staged","upstream":{"name":"origin/main","ahead":1,"behind":2},"stash":0,"changes":null}"#
+        );
+    }
+
+    #[test]
+    fn test_json_escapes_branch_name() {
+        let branch = Branch {
+            head: HeadKind::Unborn,
+            status: Status::Unstaged,
+            ..branch("feature/\"quoted\"\\")
+        };
+        let actual = Mode::Json.format(&branch, COLORED);
+        assert_eq!(
+            actual,
+            r#"{"version":1,"name":"feature/\"quoted\"\\","action":null,"progress":null,"head":"unborn","status":"unstaged","upstream":null,"stash":0,"changes":null}"#
+        );
+    }
+
+    #[test]
+    fn test_stdout_stash() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            stash: 3,
+            ..branch("main")
+        };
+        let actual = Mode::Stdout.format(&branch, COLORED);
+        assert_eq!(actual, format!("{} $3", "main".green()));
+    }
+
+    #[test]
+    fn test_zsh_stash_after_ahead_behind() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 1,
+                behind: 0,
+            }),
+            stash: 2,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{green}main%f ↑1 $2");
+    }
+
+    #[test]
+    fn test_bash_escapes_stash_marker() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            stash: 1,
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, COLORED);
+        assert_eq!(actual, r"\[\e[32m\]main\[\e[39m\] \\$1");
+    }
+
+    #[test]
+    fn test_json_unknown() {
+        let branch = Branch {
+            status: Status::Unknown,
+            ..branch("main")
+        };
+        let actual = Mode::Json.format(&branch, COLORED);
+        assert_eq!(
+            actual,
+            r#"{"version":1,"name":"main","action":null,"progress":null,"head":"symbolic","status":"unknown","upstream":null,"stash":0,"changes":null}"#
+        );
+    }
+
+    #[test]
+    fn test_json_failed() {
+        let branch = Branch {
+            status: Status::Failed,
+            ..branch("main")
+        };
+        let actual = Mode::Json.format(&branch, COLORED);
+        assert_eq!(
+            actual,
+            r#"{"version":1,"name":"main","action":null,"progress":null,"head":"symbolic","status":"failed","upstream":null,"stash":0,"changes":null}"#
+        );
+    }
+
+    #[test]
+    fn test_json_stash() {
+        let branch = Branch {
+            stash: 2,
+            ..branch("main")
+        };
+        let actual = Mode::Json.format(&branch, COLORED);
+        assert_eq!(
+            actual,
+            r#"{"version":1,"name":"main","action":null,"progress":null,"head":"symbolic","status":"not_changed","upstream":null,"stash":2,"changes":null}"#
+        );
+    }
+
+    #[test]
+    fn test_zsh_changes() {
+        let branch = Branch {
+            status: Status::Conflicted,
+            changes: Some(Changes {
+                staged: 3,
+                unstaged: 2,
+                conflicted: 1,
+                deleted: 0,
+                untracked: 4,
+            }),
+            stash: 1,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{red}main%f +3 ~2 !1 ?4 $1");
+    }
+
+    #[test]
+    fn test_zsh_omits_changes_when_clean() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            changes: Some(Changes::default()),
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{green}main%f");
+    }
+
+    #[test]
+    fn test_json_changes() {
+        let branch = Branch {
+            status: Status::Unstaged,
+            changes: Some(Changes {
+                staged: 0,
+                unstaged: 1,
+                conflicted: 0,
+                deleted: 2,
+                untracked: 0,
+            }),
+            ..branch("main")
+        };
+        let actual = Mode::Json.format(&branch, COLORED);
+        assert_eq!(
+            actual,
+            r#"{"version":1,"name":"main","action":null,"progress":null,"head":"symbolic","status":"unstaged","upstream":null,"stash":0,"changes":{"staged":0,"unstaged":1,"conflicted":0,"deleted":2,"untracked":0}}"#
+        );
+    }
+
+    #[test]
+    fn test_sgr_modes_status_colors() {
+        for mode in SGR_MODES {
+            for (status, sgr) in [
+                (Status::NotChanged, 32),
+                (Status::Staged, 33),
+                (Status::Untracked, 36),
+                (Status::Unstaged, 31),
+                (Status::Conflicted, 31),
+                (Status::Unknown, 34),
+                (Status::Failed, 35),
+            ] {
+                let branch = Branch {
+                    status: status.clone(),
+                    ..branch("main")
+                };
+                let actual = mode.format(&branch, COLORED);
+                assert_eq!(
+                    actual,
+                    format!("\x1b[{sgr}mmain\x1b[39m"),
+                    "{mode:?} {status:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sgr_modes_ahead_behind() {
+        let branch = Branch {
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 1,
+                behind: 2,
+            }),
+            ..branch("main")
+        };
+        for mode in SGR_MODES {
+            let actual = mode.format(&branch, COLORED);
+            assert_eq!(actual, "\x1b[32mmain\x1b[39m ↑1 ↓2", "{mode:?}");
+        }
+    }
+
+    /// The modes that emit plain SGR sequences and never escape.
+    const SGR_MODES: [Mode; 3] = [Mode::Fish, Mode::PowerShell, Mode::Nushell];
+
+    #[test]
+    fn test_sgr_modes_keep_special_characters_in_branch_name() {
+        let branch = branch("feature/$(id)%`n`#!\\");
+        for mode in SGR_MODES {
+            let actual = mode.format(&branch, COLORED);
+            assert_eq!(actual, "\x1b[32mfeature/$(id)%`n`#!\\\x1b[39m", "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_fish_status_sequence_replaces_color() {
+        let palette = Palette {
+            sequences: vec![(Status::Staged, "\x1b[1;92m".to_owned())],
+            ..Palette::DEFAULT
+        };
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let actual = Mode::Fish.format(&branch, Some(&palette));
+        assert_eq!(actual, "\x1b[1;92mmain\x1b[0m");
+    }
+
+    #[test]
+    fn test_tmux_not_changed() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("main")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=green]main#[default]");
+    }
+
+    #[test]
+    fn test_tmux_staged() {
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=yellow]main#[default]");
+    }
+
+    #[test]
+    fn test_tmux_untracked() {
+        let branch = Branch {
+            status: Status::Untracked,
+            ..branch("main")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=cyan]main#[default]");
+    }
+
+    #[test]
+    fn test_tmux_unstaged() {
+        let branch = Branch {
+            status: Status::Unstaged,
+            ..branch("main")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=red]main#[default]");
+    }
+
+    #[test]
+    fn test_tmux_conflicted() {
+        let branch = Branch {
+            status: Status::Conflicted,
+            ..branch("main")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=red]main#[default]");
+    }
+
+    #[test]
+    fn test_tmux_unknown() {
+        let branch = Branch {
+            status: Status::Unknown,
+            ..branch("main")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=blue]main#[default]");
+    }
+
+    #[test]
+    fn test_tmux_failed() {
+        let branch = Branch {
+            status: Status::Failed,
+            ..branch("main")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=magenta]main#[default]");
+    }
+
+    #[test]
+    fn test_tmux_ahead_behind() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 1,
+                behind: 2,
+            }),
+            ..branch("main")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=green]main#[default] ↑1 ↓2");
+    }
+
+    #[test]
+    fn test_tmux_escapes_hash_in_branch_name() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("issue#12")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=green]issue##12#[default]");
+    }
+
+    #[test]
+    fn test_tmux_escapes_style_in_branch_name() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("#[fg=red]")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=green]##[fg=red]#[default]");
+    }
+
+    #[test]
+    fn test_tmux_escapes_trailing_hash_in_branch_name() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("main#")
+        };
+        let actual = Mode::Tmux.format(&branch, COLORED);
+        assert_eq!(actual, "#[fg=green]main###[default]");
+    }
+
+    #[test]
+    fn test_powershell_accepts_pwsh_alias() {
+        assert_eq!(Mode::from_str("pwsh", false), Ok(Mode::PowerShell));
+        assert_eq!(Mode::from_str("powershell", false), Ok(Mode::PowerShell));
+    }
+
+    #[test]
+    fn test_nushell_conflicted_with_stash() {
+        let branch = Branch {
+            status: Status::Conflicted,
+            stash: 2,
+            ..branch("main")
+        };
+        let actual = Mode::Nushell.format(&branch, COLORED);
+        assert_eq!(actual, "\x1b[31mmain\x1b[39m $2");
+    }
+
+    #[test]
+    fn test_nuon_record() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("main")
+        };
+        let actual = Mode::Nuon.format(&branch, None);
+        assert_eq!(
+            actual,
+            r#"{branch: "main", action: null, status: "not_changed"}"#
+        );
+    }
+
+    #[test]
+    fn test_nuon_colored_record() {
+        let branch = Branch {
+            action: Some("rebase-i".to_owned()),
+            status: Status::Conflicted,
+            ..branch("feature")
+        };
+        let actual = Mode::Nuon.format(&branch, COLORED);
+        assert_eq!(
+            actual,
+            r#"{branch: "feature", action: "rebase-i", status: "conflicted", color: "red"}"#
+        );
+    }
+
+    #[test]
+    fn test_nuon_escapes_branch_name() {
+        let branch = branch("a\"b\\c\u{1b}");
+        let actual = Mode::Nuon.format(&branch, None);
+        assert!(actual.contains(r#"branch: "a\"b\\c\u{1b}""#), "{actual}");
+    }
+
+    #[test]
+    fn test_nu_ansi() {
+        for (color, expected) in [
+            (Color::Magenta, "magenta"),
+            (Color::Indexed(9), "#ff0000"),
+            (Color::Indexed(136), "#af8700"),
+            (Color::Indexed(244), "#808080"),
+            (Color::Rgb(0x12, 0xab, 0xef), "#12abef"),
+        ] {
+            assert_eq!(nu_ansi(color), expected);
+        }
+    }
+
+    #[test]
+    fn test_nuon_ignores_template() -> Result<(), ParseTemplateError> {
+        let branch = branch("main");
+        let template = "plain text".parse::<Template>()?;
+        let actual = Mode::Nuon.format_template(&branch, &template, COLORED);
+        assert_eq!(actual, Mode::Nuon.format(&branch, COLORED));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tcsh_not_changed() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("main")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[32m%}main%{\x1b[39m%}");
+    }
+
+    #[test]
+    fn test_tcsh_staged() {
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[33m%}main%{\x1b[39m%}");
+    }
+
+    #[test]
+    fn test_tcsh_untracked() {
+        let branch = Branch {
+            status: Status::Untracked,
+            ..branch("main")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[36m%}main%{\x1b[39m%}");
+    }
+
+    #[test]
+    fn test_tcsh_unstaged() {
+        let branch = Branch {
+            status: Status::Unstaged,
+            ..branch("main")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[31m%}main%{\x1b[39m%}");
+    }
+
+    #[test]
+    fn test_tcsh_conflicted() {
+        let branch = Branch {
+            status: Status::Conflicted,
+            ..branch("main")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[31m%}main%{\x1b[39m%}");
+    }
+
+    #[test]
+    fn test_tcsh_unknown() {
+        let branch = Branch {
+            status: Status::Unknown,
+            ..branch("main")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[34m%}main%{\x1b[39m%}");
+    }
+
+    #[test]
+    fn test_tcsh_failed() {
+        let branch = Branch {
+            status: Status::Failed,
+            ..branch("main")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[35m%}main%{\x1b[39m%}");
+    }
+
+    #[test]
+    fn test_tcsh_escapes_percent_in_branch_name() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("feature/%n")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[32m%}feature/%%n%{\x1b[39m%}");
+    }
+
+    #[test]
+    fn test_tcsh_escapes_exclamation_in_branch_name() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("wip!")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[32m%}wip\\!%{\x1b[39m%}");
+    }
+
+    #[test]
+    fn test_tcsh_escapes_conflict_marker() {
+        let branch = Branch {
+            status: Status::Conflicted,
+            changes: Some(Changes {
+                conflicted: 1,
+                ..Changes::default()
+            }),
+            ..branch("main")
+        };
+        let actual = Mode::Tcsh.format(&branch, COLORED);
+        assert_eq!(actual, "%{\x1b[31m%}main%{\x1b[39m%} \\!1");
+    }
+
+    #[test]
+    fn test_zsh_without_color_shows_marker() {
+        let branch = Branch {
+            status: Status::Unstaged,
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 1,
+                behind: 0,
+            }),
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, None);
+        assert_eq!(actual, "main* ↑1");
+    }
+
+    #[test]
+    fn test_stdout_without_color_has_no_marker_when_not_changed() {
+        let branch = Branch {
+            status: Status::NotChanged,
+            ..branch("main")
+        };
+        let actual = Mode::Stdout.format(&branch, None);
+        assert_eq!(actual, "main");
+    }
+
+    #[test]
+    fn test_tcsh_without_color_escapes_marker() {
+        let branch = Branch {
+            status: Status::Conflicted,
+            ..branch("main")
+        };
+        let actual = Mode::Tcsh.format(&branch, None);
+        assert_eq!(actual, r"main\!");
+    }
+
+    #[test]
+    fn test_template_without_color_ignores_color_specs() -> Result<(), ParseTemplateError> {
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let template = "x{branch:status}".parse::<Template>()?;
+        let actual = Mode::Zsh.format_template(&branch, &template, None);
+        assert_eq!(actual, "xmain");
+        Ok(())
+    }
+
+    #[test]
+    fn test_zsh_indexed_color() {
+        let palette = Palette {
+            staged: Color::Indexed(136),
+            ..Palette::DEFAULT
+        };
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, Some(&palette));
+        assert_eq!(actual, "%F{136}main%f");
+    }
+
+    #[test]
+    fn test_zsh_rgb_color() {
+        let palette = Palette {
+            unstaged: Color::Rgb(0xdc, 0x32, 0x2f),
+            ..Palette::DEFAULT
+        };
+        let branch = Branch {
+            status: Status::Unstaged,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, Some(&palette));
+        assert_eq!(actual, "%F{#dc322f}main%f");
+    }
+
+    #[test]
+    fn test_bash_indexed_color() {
+        let palette = Palette {
+            staged: Color::Indexed(136),
+            ..Palette::DEFAULT
+        };
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let actual = Mode::Bash.format(&branch, Some(&palette));
+        assert_eq!(actual, r"\[\e[38;5;136m\]main\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_tmux_indexed_color() {
+        let palette = Palette {
+            staged: Color::Indexed(136),
+            ..Palette::DEFAULT
+        };
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let actual = Mode::Tmux.format(&branch, Some(&palette));
+        assert_eq!(actual, "#[fg=colour136]main#[default]");
+    }
+
+    #[test]
+    fn test_stdout_rgb_color() {
+        let palette = Palette {
+            staged: Color::Rgb(1, 2, 3),
+            ..Palette::DEFAULT
+        };
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let actual = Mode::Stdout.format(&branch, Some(&palette));
+        assert_eq!(actual, "\x1b[38;2;1;2;3mmain\x1b[39m");
+    }
+
+    #[test]
+    fn test_zsh_action_color() {
+        let palette = Palette {
+            action: Some(Color::Magenta),
+            ..Palette::DEFAULT
+        };
+        let branch = Branch {
+            action: Some("merge".to_owned()),
+            status: Status::Conflicted,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, Some(&palette));
+        assert_eq!(actual, "%F{red}main%f%F{magenta}:merge%f");
+    }
+
+    #[test]
+    fn test_zsh_action_without_action_color() {
+        let branch = Branch {
+            action: Some("merge".to_owned()),
+            status: Status::Conflicted,
+            ..branch("main")
+        };
+        let actual = Mode::Zsh.format(&branch, COLORED);
+        assert_eq!(actual, "%F{red}main:merge%f");
+    }
 }
@@ -0,0 +1,439 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Output identical to `git status --porcelain=v2 --branch`, for scripts that
+//! parse it.
+//!
+//! Renamed and copied entries (`2`) are not supported. Git detects renames
+//! unless `status.renames` (or else `diff.renames`) is false, so whenever a
+//! deletion and an addition on the same side could be paired up as a rename
+//! (or, with `copies`, an addition and any other change as a copy), this is
+//! an error rather than silently different output. With rename detection off,
+//! they are listed separately, like git does.
+//! Submodules are reported like any other entry, without their own status
+//! (`N...`). Everything else, including the order of the
+//! entries, the quoting of paths (`core.quotePath`) and paths relative to the
+//! directory asked about, follows git.
+
+use std::path::{Component, Path};
+
+use crate::branch::HeadKind;
+use crate::error::Error;
+use crate::repository::Repository;
+
+/// The id written for an absent object.
+const NULL_ID: &str = "0000000000000000000000000000000000000000";
+
+/// The mode and id of a file in HEAD, the index or a conflict stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Object {
+    /// The git mode, e.g. `0o100644`.
+    pub mode: u32,
+    /// The object id, in hex.
+    pub id: String,
+}
+
+/// A path with changes, as listed by `git status --porcelain=v2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    /// An ordinary changed entry (`1`).
+    Changed {
+        /// The path from the worktree root.
+        path: Vec<u8>,
+        /// The change between HEAD and the index, e.g. `M`, or `.` if none.
+        staged: char,
+        /// The change between the index and the worktree, or `.` if none.
+        unstaged: char,
+        head: Option<Object>,
+        index: Option<Object>,
+        /// The mode in the worktree, or 0 if the file is not there.
+        worktree_mode: u32,
+    },
+    /// An unmerged entry (`u`), with its base, ours and theirs stages.
+    Unmerged {
+        path: Vec<u8>,
+        stages: [Option<Object>; 3],
+        worktree_mode: u32,
+    },
+    /// An untracked file, or a directory holding only untracked files
+    /// (`?`). Directories end with `/`.
+    Untracked { path: Vec<u8> },
+}
+
+impl Entry {
+    const fn path(&self) -> &Vec<u8> {
+        match self {
+            Self::Changed { path, .. } | Self::Unmerged { path, .. } | Self::Untracked { path } => {
+                path
+            }
+        }
+    }
+}
+
+/// Which renames `git status` detects, as set by `status.renames` or
+/// `diff.renames`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Renames {
+    /// None: deletions and additions are listed separately.
+    Off,
+    /// Renames, pairing a deletion with an addition.
+    Renames,
+    /// Renames and copies, pairing an addition with any other change.
+    Copies,
+}
+
+impl Renames {
+    /// Whether `entries` could hold a rename or copy. Git only pairs up
+    /// changes on the same side, and those with similar contents, so this
+    /// may err on the side of yes.
+    fn possible(self, entries: &[Entry]) -> bool {
+        let pairs = |changes: Vec<char>| {
+            let sources: &[char] = match self {
+                Self::Off => return false,
+                Self::Renames => &['D'],
+                Self::Copies => &['D', 'M', 'T'],
+            };
+            changes.contains(&'A') && changes.iter().any(|change| sources.contains(change))
+        };
+        let changes = |side: fn(&Entry) -> Option<char>| entries.iter().filter_map(side).collect();
+        pairs(changes(|entry| match entry {
+            Entry::Changed { staged, .. } => Some(*staged),
+            _ => None,
+        })) || pairs(changes(|entry| match entry {
+            Entry::Changed { unstaged, .. } => Some(*unstaged),
+            _ => None,
+        }))
+    }
+}
+
+/// Everything printed by `git status --porcelain=v2 --branch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// The commit HEAD points at, or `None` on an unborn branch.
+    pub oid: Option<String>,
+    /// The branch name, or `None` if HEAD is detached.
+    pub head: Option<String>,
+    /// The configured upstream, e.g. `origin/main`, even if it was never
+    /// fetched.
+    pub upstream: Option<String>,
+    /// The commits ahead of and behind the upstream, if it was fetched.
+    pub ahead_behind: Option<(usize, usize)>,
+    /// The changed entries, in any order.
+    pub entries: Vec<Entry>,
+    /// Whether bytes above 0x7f in paths are quoted (`core.quotePath`).
+    pub quote_path: bool,
+}
+
+impl Status {
+    /// Read the status of `repo`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if HEAD, the index or the worktree cannot be read, or
+    /// git could report a rename or copy.
+    pub fn collect(repo: &Repository) -> Result<Self, Error> {
+        let entries = repo.porcelain_entries()?;
+        if repo.renames().possible(&entries) {
+            return Err(Error::Renames);
+        }
+        let detached = repo.head_kind()? == HeadKind::Detached;
+        Ok(Self {
+            oid: repo.head_id(),
+            head: if detached { None } else { Some(repo.branch()?) },
+            upstream: repo.upstream_name()?,
+            ahead_behind: repo
+                .upstream()?
+                .map(|upstream| (upstream.ahead, upstream.behind)),
+            entries,
+            quote_path: repo.quote_path(),
+        })
+    }
+
+    /// The output for `dir`, whose paths are relative to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no repository is found at or above `dir`, or its
+    /// status cannot be read.
+    pub fn render(dir: &Path) -> Result<Vec<u8>, Error> {
+        let repo = Repository::discover(dir)?;
+        let status = Self::collect(&repo)?;
+        let prefix = repo
+            .workdir()
+            .and_then(|workdir| {
+                let dir = dir.canonicalize().ok()?;
+                let workdir = workdir.canonicalize().ok()?;
+                Some(dir.strip_prefix(workdir).ok()?.to_path_buf())
+            })
+            .unwrap_or_default();
+        Ok(status.format(&prefix))
+    }
+
+    /// The output, with paths relative to `prefix`, the directory asked
+    /// about relative to the worktree root.
+    #[must_use]
+    pub fn format(&self, prefix: &Path) -> Vec<u8> {
+        let mut out = Vec::new();
+        let oid = self.oid.as_deref().unwrap_or("(initial)");
+        out.extend(format!("# branch.oid {oid}\n").bytes());
+        let head = self.head.as_deref().unwrap_or("(detached)");
+        out.extend(format!("# branch.head {head}\n").bytes());
+        if let Some(upstream) = &self.upstream {
+            out.extend(format!("# branch.upstream {upstream}\n").bytes());
+            if let Some((ahead, behind)) = self.ahead_behind {
+                out.extend(format!("# branch.ab +{ahead} -{behind}\n").bytes());
+            }
+        }
+
+        // Changed and unmerged entries come first, then untracked ones, each
+        // sorted by path.
+        let mut entries: Vec<&Entry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| (matches!(entry, Entry::Untracked { .. }), entry.path()));
+        for entry in entries {
+            let path = self.quote(&relative(entry.path(), prefix));
+            let line = match entry {
+                Entry::Changed {
+                    staged,
+                    unstaged,
+                    head,
+                    index,
+                    worktree_mode,
+                    ..
+                } => format!(
+                    "1 {staged}{unstaged} N... {:06o} {:06o} {worktree_mode:06o} {} {} ",
+                    mode(head.as_ref()),
+                    mode(index.as_ref()),
+                    id(head.as_ref()),
+                    id(index.as_ref()),
+                ),
+                Entry::Unmerged {
+                    stages,
+                    worktree_mode,
+                    ..
+                } => {
+                    let [base, ours, theirs] = stages.each_ref();
+                    format!(
+                        "u {} N... {:06o} {:06o} {:06o} {worktree_mode:06o} {} {} {} ",
+                        conflict(stages),
+                        mode(base.as_ref()),
+                        mode(ours.as_ref()),
+                        mode(theirs.as_ref()),
+                        id(base.as_ref()),
+                        id(ours.as_ref()),
+                        id(theirs.as_ref()),
+                    )
+                }
+                Entry::Untracked { .. } => "? ".to_owned(),
+            };
+            out.extend(line.bytes());
+            out.extend(path);
+            out.push(b'\n');
+        }
+        out
+    }
+
+    /// `path` as git writes it: in double quotes with C-style escapes if it
+    /// contains control characters, `"` or `\`, or bytes above 0x7f unless
+    /// `core.quotePath` is off.
+    fn quote(&self, path: &[u8]) -> Vec<u8> {
+        let needs_quoting = |byte: u8| {
+            byte < 0x20
+                || byte == b'"'
+                || byte == b'\\'
+                || byte == 0x7f
+                || (self.quote_path && byte > 0x7f)
+        };
+        if !path.iter().copied().any(needs_quoting) {
+            return path.to_vec();
+        }
+        let mut quoted = vec![b'"'];
+        for &byte in path {
+            match byte {
+                0x07 => quoted.extend(b"\\a"),
+                0x08 => quoted.extend(b"\\b"),
+                b'\t' => quoted.extend(b"\\t"),
+                b'\n' => quoted.extend(b"\\n"),
+                0x0b => quoted.extend(b"\\v"),
+                0x0c => quoted.extend(b"\\f"),
+                b'\r' => quoted.extend(b"\\r"),
+                b'"' => quoted.extend(b"\\\""),
+                b'\\' => quoted.extend(b"\\\\"),
+                byte if needs_quoting(byte) => quoted.extend(format!("\\{byte:03o}").bytes()),
+                byte => quoted.push(byte),
+            }
+        }
+        quoted.push(b'"');
+        quoted
+    }
+}
+
+fn mode(object: Option<&Object>) -> u32 {
+    object.map_or(0, |object| object.mode)
+}
+
+fn id(object: Option<&Object>) -> &str {
+    object.map_or(NULL_ID, |object| &object.id)
+}
+
+/// The two-letter kind of conflict, from which of the base, ours and theirs
+/// stages exist.
+fn conflict(stages: &[Option<Object>; 3]) -> &'static str {
+    match stages.each_ref().map(Option::is_some) {
+        [true, false, false] => "DD",
+        [false, true, false] => "AU",
+        [true, false, true] => "DU",
+        [false, false, true] => "UA",
+        [true, true, false] => "UD",
+        [false, true, true] => "AA",
+        _ => "UU",
+    }
+}
+
+/// `path`, relative to the worktree root, made relative to `prefix` with
+/// `..` as needed, like git does for paths outside the current directory.
+fn relative(path: &[u8], prefix: &Path) -> Vec<u8> {
+    let prefix: Vec<&[u8]> = prefix
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.as_encoded_bytes()),
+            _ => None,
+        })
+        .collect();
+    let mut rest = path;
+    let mut common = 0;
+    for dir in &prefix {
+        let inside = rest
+            .strip_prefix(*dir)
+            .and_then(|rest| rest.strip_prefix(b"/"));
+        match inside {
+            Some(inside) => {
+                rest = inside;
+                common += 1;
+            }
+            None => break,
+        }
+    }
+    let mut relative = b"../".repeat(prefix.len() - common);
+    relative.extend(rest);
+    if relative.is_empty() {
+        relative.extend(b"./");
+    }
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(quote_path: bool) -> Status {
+        Status {
+            oid: None,
+            head: None,
+            upstream: None,
+            ahead_behind: None,
+            entries: Vec::new(),
+            quote_path,
+        }
+    }
+
+    fn object() -> Object {
+        Object {
+            mode: 0o100_644,
+            id: NULL_ID.to_string(),
+        }
+    }
+
+    fn changed(staged: char, unstaged: char) -> Entry {
+        Entry::Changed {
+            path: b"a".to_vec(),
+            staged,
+            unstaged,
+            head: None,
+            index: None,
+            worktree_mode: 0,
+        }
+    }
+
+    #[test]
+    fn test_renames_pair_a_deletion_with_an_addition() {
+        let entries = [changed('D', '.'), changed('A', '.')];
+        assert!(Renames::Renames.possible(&entries));
+        assert!(Renames::Copies.possible(&entries));
+        assert!(!Renames::Off.possible(&entries));
+    }
+
+    #[test]
+    fn test_renames_pair_changes_on_the_same_side() {
+        assert!(Renames::Renames.possible(&[changed('.', 'D'), changed('.', 'A')]));
+        assert!(!Renames::Renames.possible(&[changed('D', '.'), changed('.', 'A')]));
+        assert!(!Renames::Renames.possible(&[changed('A', 'D')]));
+    }
+
+    #[test]
+    fn test_copies_pair_any_change_with_an_addition() {
+        let entries = [changed('M', '.'), changed('A', '.')];
+        assert!(Renames::Copies.possible(&entries));
+        assert!(!Renames::Renames.possible(&entries));
+        assert!(!Renames::Copies.possible(&[changed('M', 'D')]));
+    }
+
+    #[test]
+    fn test_quote_leaves_plain_paths_alone() {
+        assert_eq!(status(true).quote(b"dir/a b.txt"), b"dir/a b.txt");
+    }
+
+    #[test]
+    fn test_quote_escapes_special_bytes() {
+        assert_eq!(status(true).quote(b"a\"b\\c\td"), br#""a\"b\\c\td""#);
+        assert_eq!(status(true).quote(b"\x01"), br#""\001""#);
+        assert_eq!(status(true).quote("café".as_bytes()), br#""caf\303\251""#);
+    }
+
+    #[test]
+    fn test_quote_keeps_non_ascii_without_quote_path() {
+        assert_eq!(status(false).quote("café".as_bytes()), "café".as_bytes());
+        assert_eq!(status(false).quote(b"a\tb"), br#""a\tb""#);
+    }
+
+    #[test]
+    fn test_relative_walks_up_from_prefix() {
+        assert_eq!(relative(b"a/b", Path::new("")), b"a/b");
+        assert_eq!(relative(b"a/b", Path::new("a")), b"b");
+        assert_eq!(relative(b"c", Path::new("a/b")), b"../../c");
+        assert_eq!(relative(b"a/c", Path::new("a/b")), b"../c");
+        assert_eq!(relative(b"ab/c", Path::new("a")), b"../ab/c");
+    }
+
+    #[test]
+    fn test_relative_is_dot_for_the_prefix_itself() {
+        assert_eq!(relative(b"a/", Path::new("a")), b"./");
+    }
+
+    #[test]
+    fn test_conflict_follows_existing_stages() {
+        let cases = [
+            ([true, false, false], "DD"),
+            ([false, true, false], "AU"),
+            ([true, false, true], "DU"),
+            ([false, false, true], "UA"),
+            ([true, true, false], "UD"),
+            ([false, true, true], "AA"),
+            ([true, true, true], "UU"),
+        ];
+        for (exists, expected) in cases {
+            let stages = exists.map(|exists| exists.then(object));
+            assert_eq!(conflict(&stages), expected);
+        }
+    }
+}
@@ -0,0 +1,447 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Output in the shape of `__git_ps1` from git's `git-prompt.sh`, e.g.
+//! `main *+$%>|REBASE-i 2/5`, controlled by the same `GIT_PS1_*` variables.
+//!
+//! The variables have to be exported to reach this process. The state
+//! separator and `GIT_PS1_SHOWCONFLICTSTATE` are honored, while colors,
+//! sparse checkouts, `GIT_PS1_DESCRIBE_STYLE` and the `bash.*` settings are
+//! not.
+
+use std::ffi::OsString;
+
+use crate::branch::{Branch, HeadKind, Status};
+
+/// Which parts of the prompt to show, from the `GIT_PS1_*` variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Options {
+    /// `*` for unstaged and `+` for staged changes, from
+    /// `GIT_PS1_SHOWDIRTYSTATE`.
+    pub dirty_state: bool,
+    /// `$` if there are stash entries, from `GIT_PS1_SHOWSTASHSTATE`.
+    pub stash_state: bool,
+    /// `%` if there are untracked files, from `GIT_PS1_SHOWUNTRACKEDFILES`.
+    pub untracked_files: bool,
+    /// The upstream state, from `GIT_PS1_SHOWUPSTREAM`.
+    pub upstream: Option<ShowUpstream>,
+    /// `|CONFLICT` while there are unmerged paths, if
+    /// `GIT_PS1_SHOWCONFLICTSTATE` is `yes`.
+    pub conflict_state: bool,
+    /// What separates the branch from the state, from
+    /// `GIT_PS1_STATESEPARATOR`.
+    pub state_separator: String,
+}
+
+/// The words of `GIT_PS1_SHOWUPSTREAM` that change the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ShowUpstream {
+    /// `|u+1-2` after the action instead of `<>` in the state.
+    pub verbose: bool,
+    /// The name of the upstream after the verbose state.
+    pub name: bool,
+}
+
+impl Options {
+    /// The options set in the environment looked up with `var`. Like in
+    /// `git-prompt.sh`, any non-empty value enables a part.
+    pub fn from_env(var: impl Fn(&str) -> Option<OsString>) -> Self {
+        let value = |key: &str| var(key).filter(|value| !value.is_empty());
+        Self {
+            dirty_state: value("GIT_PS1_SHOWDIRTYSTATE").is_some(),
+            stash_state: value("GIT_PS1_SHOWSTASHSTATE").is_some(),
+            untracked_files: value("GIT_PS1_SHOWUNTRACKEDFILES").is_some(),
+            upstream: value("GIT_PS1_SHOWUPSTREAM").map(|words| {
+                let words = words.to_string_lossy();
+                let has = |word| words.split_whitespace().any(|w| w == word);
+                ShowUpstream {
+                    verbose: has("verbose"),
+                    name: has("name"),
+                }
+            }),
+            conflict_state: value("GIT_PS1_SHOWCONFLICTSTATE").is_some_and(|value| value == "yes"),
+            state_separator: var("GIT_PS1_STATESEPARATOR").map_or_else(
+                || " ".to_owned(),
+                |value| value.to_string_lossy().into_owned(),
+            ),
+        }
+    }
+
+    /// Whether the change counts are needed, which requires walking the
+    /// whole worktree.
+    #[must_use]
+    pub const fn needs_counts(&self) -> bool {
+        self.dirty_state || self.untracked_files
+    }
+}
+
+/// What `__git_ps1` shows for `branch`, before it is put into its format.
+///
+/// `head` is the [name of HEAD](crate::report::Report::head_name) itself, and
+/// `tag` the tag HEAD is detached at, if any; otherwise a detached HEAD is
+/// shown as `head`, its short hash, followed by `...`.
+#[must_use]
+pub fn format(branch: &Branch, head: &str, tag: Option<&str>, options: &Options) -> String {
+    let action = branch.action.as_deref();
+    // Only a rebase shows the branch being rebased, like `git-prompt.sh` does;
+    // `git am` (`am/rebase` included) shows HEAD.
+    let name = if matches!(action, Some("rebase" | "rebase-i")) {
+        branch.name.clone()
+    } else if branch.head == HeadKind::Detached {
+        tag.map_or_else(|| format!("({head}...)"), |tag| format!("({tag})"))
+    } else {
+        head.to_owned()
+    };
+
+    let changes = branch.changes.as_ref();
+    let mut state = String::new();
+    if options.dirty_state {
+        if changes.is_some_and(|c| c.unstaged + c.deleted + c.conflicted > 0) {
+            state.push('*');
+        }
+        if changes.is_some_and(|c| c.staged + c.conflicted > 0) {
+            state.push('+');
+        } else if branch.head == HeadKind::Unborn {
+            state.push('#');
+        }
+    }
+    if options.stash_state && branch.stash > 0 {
+        state.push('$');
+    }
+    if options.untracked_files && changes.is_some_and(|c| c.untracked > 0) {
+        state.push('%');
+    }
+    let upstream = branch.upstream.as_ref();
+    let verbose = options.upstream.filter(|show| show.verbose);
+    let short = options.upstream.filter(|show| !show.verbose);
+    if let Some(upstream) = upstream.filter(|_| short.is_some()) {
+        state.push_str(match (upstream.ahead, upstream.behind) {
+            (0, 0) => "=",
+            (_, 0) => ">",
+            (0, _) => "<",
+            _ => "<>",
+        });
+    }
+
+    let state = (!state.is_empty()).then(|| options.state_separator.clone() + &state);
+    let action = action.map(|action| {
+        let progress = branch
+            .progress
+            .as_ref()
+            .map_or_else(String::new, |progress| {
+                format!(" {}/{}", progress.current, progress.total)
+            });
+        format!("|{}{progress}", action_name(action))
+    });
+    let verbose = upstream.zip(verbose).map(|(upstream, show)| {
+        let counts = match (upstream.ahead, upstream.behind) {
+            (0, 0) => "=".to_owned(),
+            (ahead, 0) => format!("+{ahead}"),
+            (0, behind) => format!("-{behind}"),
+            (ahead, behind) => format!("+{ahead}-{behind}"),
+        };
+        let name = if show.name {
+            format!(" {}", upstream.name)
+        } else {
+            String::new()
+        };
+        format!("|u{counts}{name}")
+    });
+    let conflict = (options.conflict_state && branch.status == Status::Conflicted)
+        .then(|| "|CONFLICT".to_owned());
+    [Some(name), state, action, verbose, conflict]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Put `ps1` into `format` the way `__git_ps1` passes it to `printf`.
+///
+/// The first `%s` is replaced by `ps1`, any further ones by nothing, and `%%`
+/// by `%`. Other `%` sequences and backslashes are kept as they are.
+#[must_use]
+pub fn expand(format: &str, ps1: &str) -> String {
+    let mut expanded = String::with_capacity(format.len() + ps1.len());
+    let mut arg = Some(ps1);
+    let mut rest = format;
+    while let Some(at) = rest.find('%') {
+        expanded.push_str(&rest[..at]);
+        rest = &rest[at..];
+        if rest.starts_with("%s") {
+            expanded.push_str(arg.take().unwrap_or_default());
+            rest = &rest[2..];
+        } else if rest.starts_with("%%") {
+            expanded.push('%');
+            rest = &rest[2..];
+        } else {
+            expanded.push('%');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// The name `git-prompt.sh` shows for an action label of
+/// [`Repository::action`](crate::repository::Repository::action).
+fn action_name(action: &str) -> &str {
+    match action {
+        "am" => "AM",
+        "am/rebase" => "AM/REBASE",
+        "bisect" => "BISECTING",
+        "cherry" | "cherry-seq" => "CHERRY-PICKING",
+        "merge" => "MERGING",
+        "rebase" => "REBASE",
+        "rebase-i" => "REBASE-i",
+        "revert" | "revert-seq" => "REVERTING",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::branch::{Changes, Progress, Upstream};
+    use crate::test_util::branch;
+
+    fn options(vars: &[(&str, &str)]) -> Options {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        Options::from_env(|key| vars.get(key).map(OsString::from))
+    }
+
+    const ALL: &[(&str, &str)] = &[
+        ("GIT_PS1_SHOWDIRTYSTATE", "1"),
+        ("GIT_PS1_SHOWSTASHSTATE", "1"),
+        ("GIT_PS1_SHOWUNTRACKEDFILES", "1"),
+        ("GIT_PS1_SHOWUPSTREAM", "auto"),
+    ];
+
+    #[test]
+    fn test_from_env_treats_empty_values_as_unset() {
+        let options = options(&[("GIT_PS1_SHOWDIRTYSTATE", ""), ("GIT_PS1_SHOWUPSTREAM", "")]);
+        assert!(!options.dirty_state);
+        assert_eq!(options.upstream, None);
+        assert_eq!(options.state_separator, " ");
+    }
+
+    #[test]
+    fn test_from_env_reads_upstream_words() {
+        let options = options(&[("GIT_PS1_SHOWUPSTREAM", "verbose  name git")]);
+        assert_eq!(
+            options.upstream,
+            Some(ShowUpstream {
+                verbose: true,
+                name: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_env_only_shows_conflicts_for_yes() {
+        assert!(!options(&[("GIT_PS1_SHOWCONFLICTSTATE", "1")]).conflict_state);
+        assert!(options(&[("GIT_PS1_SHOWCONFLICTSTATE", "yes")]).conflict_state);
+    }
+
+    #[test]
+    fn test_format_without_options_shows_only_the_branch() {
+        let branch = Branch {
+            stash: 1,
+            changes: Some(Changes {
+                staged: 1,
+                ..Changes::default()
+            }),
+            ..branch("main")
+        };
+        assert_eq!(format(&branch, &branch.name, None, &options(&[])), "main");
+    }
+
+    #[test]
+    fn test_format_shows_state_after_separator() {
+        let branch = Branch {
+            stash: 2,
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 1,
+                behind: 2,
+            }),
+            changes: Some(Changes {
+                staged: 1,
+                deleted: 1,
+                untracked: 3,
+                ..Changes::default()
+            }),
+            ..branch("main")
+        };
+        assert_eq!(
+            format(&branch, &branch.name, None, &options(ALL)),
+            "main *+$%<>"
+        );
+        let mut vars = ALL.to_vec();
+        vars.push(("GIT_PS1_STATESEPARATOR", ""));
+        assert_eq!(
+            format(&branch, &branch.name, None, &options(&vars)),
+            "main*+$%<>"
+        );
+    }
+
+    #[test]
+    fn test_format_shows_upstream_direction() {
+        let cases = [((0, 0), "main ="), ((2, 0), "main >"), ((0, 2), "main <")];
+        for ((ahead, behind), expected) in cases {
+            let branch = Branch {
+                upstream: Some(Upstream {
+                    name: "origin/main".to_owned(),
+                    ahead,
+                    behind,
+                }),
+                ..branch("main")
+            };
+            assert_eq!(format(&branch, &branch.name, None, &options(ALL)), expected);
+        }
+    }
+
+    #[test]
+    fn test_format_shows_verbose_upstream_after_action() {
+        let branch = Branch {
+            action: Some("merge".to_owned()),
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 0,
+                behind: 3,
+            }),
+            ..branch("main")
+        };
+        let verbose = options(&[("GIT_PS1_SHOWUPSTREAM", "verbose")]);
+        assert_eq!(
+            format(&branch, &branch.name, None, &verbose),
+            "main|MERGING|u-3"
+        );
+        let named = options(&[("GIT_PS1_SHOWUPSTREAM", "verbose name")]);
+        assert_eq!(
+            format(&branch, &branch.name, None, &named),
+            "main|MERGING|u-3 origin/main"
+        );
+    }
+
+    #[test]
+    fn test_format_marks_unborn_branch_without_staged_changes() {
+        let branch = Branch {
+            head: HeadKind::Unborn,
+            changes: Some(Changes::default()),
+            ..branch("main")
+        };
+        assert_eq!(format(&branch, &branch.name, None, &options(ALL)), "main #");
+    }
+
+    #[test]
+    fn test_format_shows_detached_head_in_parentheses() {
+        let branch = Branch {
+            head: HeadKind::Detached,
+            ..branch("1a2b3c4")
+        };
+        assert_eq!(
+            format(&branch, &branch.name, None, &options(&[])),
+            "(1a2b3c4...)"
+        );
+        assert_eq!(
+            format(&branch, &branch.name, Some("v1.0"), &options(&[])),
+            "(v1.0)"
+        );
+    }
+
+    #[test]
+    fn test_format_shows_rebased_branch_with_progress() {
+        let branch = Branch {
+            action: Some("rebase-i".to_owned()),
+            progress: Some(Progress {
+                current: 2,
+                total: 5,
+            }),
+            head: HeadKind::Detached,
+            status: Status::Conflicted,
+            changes: Some(Changes {
+                conflicted: 1,
+                ..Changes::default()
+            }),
+            ..branch("main")
+        };
+        let mut vars = ALL.to_vec();
+        vars.push(("GIT_PS1_SHOWCONFLICTSTATE", "yes"));
+        assert_eq!(
+            format(&branch, &branch.name, None, &options(&vars)),
+            "main *+|REBASE-i 2/5|CONFLICT"
+        );
+    }
+
+    #[test]
+    fn test_format_shows_head_during_am_rebase() {
+        let branch = Branch {
+            action: Some("am/rebase".to_owned()),
+            head: HeadKind::Detached,
+            ..branch("feature")
+        };
+        assert_eq!(
+            format(&branch, "1a2b3c4", None, &options(&[])),
+            "(1a2b3c4...)|AM/REBASE"
+        );
+        let branch = Branch {
+            head: HeadKind::Symbolic,
+            ..branch
+        };
+        assert_eq!(
+            format(&branch, "main", None, &options(&[])),
+            "main|AM/REBASE"
+        );
+    }
+
+    #[test]
+    fn test_expand_replaces_first_placeholder() {
+        assert_eq!(expand(" (%s)", "main"), " (main)");
+        assert_eq!(expand("[%s|%s]", "main"), "[main|]");
+        assert_eq!(expand("no placeholder", "main"), "no placeholder");
+    }
+
+    #[test]
+    fn test_expand_unescapes_percent() {
+        assert_eq!(expand("(%s) 100%%", "main"), "(main) 100%");
+        assert_eq!(expand("%%s %s", "main"), "%s main");
+        assert_eq!(expand("50% %s %", "main"), "50% main %");
+    }
+
+    #[test]
+    fn test_expand_keeps_percent_in_ps1() {
+        assert_eq!(expand("(%s)", "main %%"), "(main %%)");
+    }
+
+    #[test]
+    fn test_action_name_matches_git_prompt() {
+        let cases = [
+            ("am", "AM"),
+            ("am/rebase", "AM/REBASE"),
+            ("bisect", "BISECTING"),
+            ("cherry-seq", "CHERRY-PICKING"),
+            ("merge", "MERGING"),
+            ("rebase", "REBASE"),
+            ("revert", "REVERTING"),
+        ];
+        for (action, expected) in cases {
+            assert_eq!(action_name(action), expected);
+        }
+    }
+}
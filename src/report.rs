@@ -0,0 +1,203 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::branch::{Branch, HeadKind, Status};
+use crate::error::Error;
+use crate::repository::Repository;
+
+/// Everything needed to print a [`Branch`], gathered in one go so it can be
+/// computed by the daemon as well as in-process.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub branch: Branch,
+    /// Whether every part of [`branch`](Self::branch) could be determined.
+    /// The parts that could not are shown as placeholders, e.g.
+    /// [`Status::Failed`].
+    pub complete: bool,
+    /// The value of git's `color.ui`, if set.
+    pub color_ui: Option<String>,
+    /// The id of the commit HEAD points at, or `None` on an unborn branch.
+    pub revision: Option<String>,
+    /// The root of the worktree, or `None` for a bare repository.
+    pub root: Option<PathBuf>,
+    /// The name of HEAD itself, which differs from the branch name while a
+    /// rebase shows the branch being rebased. `ps1` shows it for `git am`.
+    pub head_name: String,
+    /// A tag pointing exactly at HEAD when it is detached, which `ps1` shows
+    /// instead of the commit id.
+    pub tag: Option<String>,
+}
+
+impl Report {
+    /// Read the branch, its status and everything around it from `repo`.
+    /// With `counts`, the whole worktree is walked to count the changes,
+    /// which also settles the status.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the branch name or HEAD cannot be read. Only the
+    /// name is essential: everything else falls back to a placeholder on
+    /// failure (e.g. a corrupt index), recorded in
+    /// [`complete`](Self::complete).
+    pub fn collect(repo: &Repository, counts: bool) -> Result<Self, Error> {
+        let name = repo.branch()?;
+        let head = repo.head_kind()?;
+        let tag = (head == HeadKind::Detached)
+            .then(|| repo.tag_name())
+            .flatten();
+        let mut complete = true;
+        // Counting walks the whole worktree, but then also settles the status,
+        // so the early-exit scan is only run when counts were not requested.
+        let (status, changes) = if counts {
+            match repo.changes() {
+                Ok(Some(changes)) => (changes.status(), Some(changes)),
+                Ok(None) => (Status::Unknown, None),
+                Err(_) => {
+                    complete = false;
+                    (Status::Failed, None)
+                }
+            }
+        } else {
+            let status = or_fallback(repo.branch_status(), Status::Failed, &mut complete);
+            (status, None)
+        };
+        let branch = Branch {
+            name,
+            action: repo.action(),
+            progress: repo.progress(),
+            head,
+            status,
+            upstream: or_fallback(repo.upstream(), None, &mut complete),
+            stash: or_fallback(repo.stash_count(), 0, &mut complete),
+            changes,
+        };
+        Ok(Self {
+            branch,
+            complete,
+            color_ui: repo.color_ui(),
+            revision: repo.head_id(),
+            root: repo.workdir().map(Path::to_path_buf),
+            head_name: repo.head_name()?,
+            tag,
+        })
+    }
+}
+
+/// The value of `result`, or `fallback` after recording the failure in
+/// `complete`.
+fn or_fallback<T>(result: Result<T, Error>, fallback: T, complete: &mut bool) -> T {
+    result.unwrap_or_else(|_| {
+        *complete = false;
+        fallback
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    use super::*;
+    use crate::branch::Changes;
+    use crate::test_util::git;
+
+    fn staged_repo() -> Result<TempDir> {
+        let dir = TempDir::new()?;
+        git(dir.path(), &["init", "-q", "-b", "main"]);
+        git(dir.path(), &["config", "color.ui", "never"]);
+        dir.child("f").write_str("a\n")?;
+        git(dir.path(), &["add", "f"]);
+        Ok(dir)
+    }
+
+    #[test]
+    fn collect_reads_branch_status_and_color_ui() -> Result<()> {
+        let dir = staged_repo()?;
+        let report = Report::collect(&Repository::discover(dir.path())?, false)?;
+        assert_eq!(report.branch.name, "main");
+        assert_eq!(report.branch.status, Status::Staged);
+        assert_eq!(report.branch.changes, None);
+        assert!(report.complete);
+        assert_eq!(report.color_ui.as_deref(), Some("never"));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn collect_reads_revision_and_root() -> Result<()> {
+        let dir = staged_repo()?;
+        let report = Report::collect(&Repository::discover(dir.path())?, false)?;
+        assert_eq!(report.revision, None);
+        git(
+            dir.path(),
+            &[
+                "-c",
+                "user.name=a",
+                "-c",
+                "user.email=a@b",
+                "commit",
+                "-qm",
+                "a",
+            ],
+        );
+        let report = Report::collect(&Repository::discover(dir.path())?, false)?;
+        assert_eq!(report.revision.map(|revision| revision.len()), Some(40));
+        let root = report.root.map(|root| root.canonicalize()).transpose()?;
+        assert_eq!(root, Some(dir.path().canonicalize()?));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn collect_reads_tag_of_detached_head() -> Result<()> {
+        let dir = staged_repo()?;
+        git(dir.path(), &["commit", "-qm", "a"]);
+        git(dir.path(), &["tag", "v1"]);
+        let report = Report::collect(&Repository::discover(dir.path())?, false)?;
+        assert_eq!(report.tag, None);
+        git(dir.path(), &["checkout", "-q", "--detach"]);
+        let report = Report::collect(&Repository::discover(dir.path())?, false)?;
+        assert_eq!(report.tag.as_deref(), Some("v1"));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn collect_reads_head_name_during_rebase() -> Result<()> {
+        let dir = staged_repo()?;
+        git(dir.path(), &["commit", "-qm", "a"]);
+        dir.child(".git/rebase-apply/head-name")
+            .write_str("refs/heads/feature\n")?;
+        dir.child(".git/rebase-apply/rebasing").touch()?;
+        let report = Report::collect(&Repository::discover(dir.path())?, false)?;
+        assert_eq!(report.branch.name, "feature");
+        assert_eq!(report.head_name, "main");
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn collect_counts_changes() -> Result<()> {
+        let dir = staged_repo()?;
+        let report = Report::collect(&Repository::discover(dir.path())?, true)?;
+        let expected = Changes {
+            staged: 1,
+            ..Changes::default()
+        };
+        assert_eq!(report.branch.changes, Some(expected));
+        assert_eq!(report.branch.status, Status::Staged);
+        dir.close().map_err(Into::into)
+    }
+}
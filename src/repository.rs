@@ -12,25 +12,44 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use gix::bstr::BString;
+use gix::bstr::{BString, ByteSlice as _};
 use gix::commit::describe::SelectRef;
+use gix::diff::index::Change as IndexChange;
+use gix::dir::Entry as DirEntry;
+use gix::dir::entry::{Kind as DirEntryKind, Status as DirEntryStatus};
 use gix::head::Kind::{Detached, Symbolic, Unborn};
+use gix::index::File as IndexFile;
+use gix::index::entry::Mode as EntryMode;
+use gix::path::{from_bstr, into_bstr};
 use gix::progress::Discard;
+use gix::remote::Direction;
 use gix::state::InProgress;
 use gix::status::index_worktree::Item as IndexWorktreeItem;
-use gix::status::plumbing::index_as_worktree::EntryStatus;
+use gix::status::plumbing::index_as_worktree::{Change, EntryStatus};
 use gix::status::tree_index::TrackRenames;
-use gix::status::{Item as StatusItem, UntrackedFiles};
+use gix::status::{Item as StatusItem, Iter as StatusIter, UntrackedFiles};
+use gix::worktree::stack::state::ignore::Source as IgnoreSource;
 
-use crate::branch::Status;
+use crate::branch::{Changes, HeadKind, Progress, Remote, Status, Upstream};
 use crate::error::Error;
+use crate::porcelain::{Entry, Object, Renames};
 
 /// A thin wrapper over [`gix::Repository`] exposing only the operations this tool
 /// needs, keeping all `gix` types contained to this module.
-pub struct Repository(gix::Repository);
+#[derive(Clone)]
+pub struct Repository {
+    repo: gix::Repository,
+    untracked_files: bool,
+    should_interrupt: Option<Arc<AtomicBool>>,
+}
 
 impl Repository {
     /// Discover a repository starting from `path` and walking up to the root.
@@ -39,7 +58,34 @@ impl Repository {
     ///
     /// Returns an error if no git repository is found at or above `path`.
     pub fn discover(path: impl AsRef<Path>) -> Result<Self, Error> {
-        Ok(Self(gix::discover(path)?))
+        Ok(Self {
+            repo: gix::discover(path)?,
+            untracked_files: false,
+            should_interrupt: None,
+        })
+    }
+
+    /// Whether [`branch_status`](Self::branch_status) reports untracked files
+    /// as [`Status::Untracked`]. Off by default, since it requires walking the
+    /// worktree directories.
+    ///
+    /// Even when enabled, a repository configured with
+    /// `status.showUntrackedFiles = no` (or `false`) keeps ignoring untracked
+    /// files, like `git status` does.
+    #[must_use]
+    pub const fn untracked_files(mut self, enabled: bool) -> Self {
+        self.untracked_files = enabled;
+        self
+    }
+
+    /// A flag that cancels the worktree scan of
+    /// [`branch_status`](Self::branch_status) and [`changes`](Self::changes)
+    /// once it is set, e.g. by a timer, making them report the status as
+    /// undetermined instead of blocking.
+    #[must_use]
+    pub fn should_interrupt(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.should_interrupt = Some(flag);
+        self
     }
 
     /// The branch name to display, optionally suffixed with the in-progress
@@ -49,37 +95,89 @@ impl Repository {
     ///
     /// Returns an error if the HEAD reference cannot be resolved.
     pub fn branch_name(&self) -> Result<String, Error> {
-        let state = self.0.state();
+        let branch = self.branch()?;
+        match self.action() {
+            Some(action) => Ok(branch + ":" + &action),
+            None => Ok(branch),
+        }
+    }
 
+    /// The branch name without the in-progress action. During a rebase this
+    /// is the branch being rebased rather than the detached HEAD.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HEAD reference cannot be resolved.
+    pub fn branch(&self) -> Result<String, Error> {
         // Only consult the on-disk head-name file when gix independently
         // confirms a rebase is in progress. Reading it unconditionally can
         // produce a stale branch name if the file was left behind after an
         // aborted rebase while gix no longer detects any rebase state.
-        let rebase_name = state
-            .as_ref()
-            .filter(|state| state.is_rebase())
+        let rebase_name = self
+            .repo
+            .state()
+            .filter(InProgressExt::is_rebase)
             .and_then(|_| self.rebase_head_name());
 
-        let branch = match rebase_name {
-            Some(name) => name,
-            None => self.name_from_head()?,
-        };
+        rebase_name.map_or_else(|| self.head_name(), Ok)
+    }
 
-        match state {
-            Some(state) => Ok(branch + ":" + state.label()),
-            None => Ok(branch),
-        }
+    /// A short label for the in-progress action (e.g. `rebase-i`), if any.
+    #[must_use]
+    pub fn action(&self) -> Option<String> {
+        self.repo.state().map(|state| state.label().to_owned())
+    }
+
+    /// How far an in-progress rebase or `git am` has got, if any.
+    ///
+    /// The merge backend (and interactive rebases) record the current step in
+    /// `rebase-merge/msgnum` and the total in `rebase-merge/end`, while the apply
+    /// backend, which `git am` also uses, records them in `rebase-apply/next` and
+    /// `rebase-apply/last`.
+    #[must_use]
+    pub fn progress(&self) -> Option<Progress> {
+        // Like `head-name`, these files are only trusted while gix confirms the
+        // operation, so leftovers from an aborted rebase are not shown.
+        self.repo
+            .state()
+            .filter(|state| state.is_rebase() || *state == InProgress::ApplyMailbox)?;
+        let git_dir = self.repo.path();
+        [
+            ("rebase-merge", "msgnum", "end"),
+            ("rebase-apply", "next", "last"),
+        ]
+        .into_iter()
+        .find_map(|(dir, current, total)| {
+            let dir = git_dir.join(dir);
+            Some(Progress {
+                current: read_number(&dir.join(current))?,
+                total: read_number(&dir.join(total))?,
+            })
+        })
     }
 
-    /// The display name derived from HEAD: the shorthand of a symbolic or unborn
-    /// ref, or for a detached HEAD a tag pointing at it, falling back to the
-    /// short hash.
+    /// What HEAD points at.
     ///
     /// # Errors
     ///
     /// Returns an error if the HEAD reference cannot be resolved.
-    fn name_from_head(&self) -> Result<String, Error> {
-        let head = self.0.head()?;
+    pub fn head_kind(&self) -> Result<HeadKind, Error> {
+        Ok(match self.repo.head()?.kind {
+            Symbolic(_) => HeadKind::Symbolic,
+            Unborn(_) => HeadKind::Unborn,
+            Detached { .. } => HeadKind::Detached,
+        })
+    }
+
+    /// The display name derived from HEAD alone, even during a rebase: the
+    /// shorthand of a symbolic or unborn ref, or for a detached HEAD a tag
+    /// pointing at it, falling back to the short hash.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HEAD reference cannot be resolved.
+    pub fn head_name(&self) -> Result<String, Error> {
+        let head = self.repo.head()?;
         Ok(match &head.kind {
             Symbolic(reference) => reference.name.shorten().to_string(),
             Unborn(name) => name.shorten().to_string(),
@@ -92,7 +190,12 @@ impl Repository {
         })
     }
 
-    /// The worst status across the working tree, ignoring untracked files.
+    /// The worst status across the working tree. Untracked files are ignored
+    /// unless enabled with [`untracked_files`](Self::untracked_files).
+    ///
+    /// Returns [`Status::Unknown`] if the scan is cancelled through
+    /// [`should_interrupt`](Self::should_interrupt) before the status is
+    /// settled.
     ///
     /// # Errors
     ///
@@ -106,26 +209,28 @@ impl Repository {
             return Ok(Status::Conflicted);
         }
 
-        let iter = self
-            .0
-            .status(Discard)?
-            .untracked_files(UntrackedFiles::None)
-            // Rename detection (on by default) reads blob contents to compute
-            // similarity, which is pure overhead here: a rename maps to the same
-            // staged/unstaged status as a separate delete and add would.
-            .index_worktree_rewrites(None)
-            .tree_index_track_renames(TrackRenames::Disabled)
-            .into_iter(Vec::<BString>::new())?;
+        // Collapsed is the cheapest mode that still reports untracked files: a
+        // single untracked directory is enough to settle the status.
+        let mut iter = self.status_iter(UntrackedFiles::Collapsed)?;
 
         // With conflicts ruled out, an unstaged change is the worst remaining
         // status, so the worktree scan can stop at the first one it finds
         // instead of walking the whole tree.
         let mut status = Status::NotChanged;
-        for item in iter {
-            match item? {
-                // HEAD <-> index: a staged change. Unstaged changes short-circuit
-                // above, so staged is the highest status this loop can settle on.
-                StatusItem::TreeIndex(_) => status = Status::Staged,
+        for item in &mut iter {
+            let item = match item {
+                Ok(item) => item,
+                Err(_) if self.is_interrupted() => return Ok(Status::Unknown),
+                Err(err) => return Err(err.into()),
+            };
+            match item {
+                // HEAD <-> index: a staged change. It must not downgrade an
+                // untracked file found earlier, since the two scans interleave.
+                StatusItem::TreeIndex(_) => {
+                    if status == Status::NotChanged {
+                        status = Status::Staged;
+                    }
+                }
                 // index <-> worktree: an unstaged change.
                 StatusItem::IndexWorktree(IndexWorktreeItem::Modification {
                     status: entry,
@@ -148,18 +253,522 @@ impl Repository {
                     );
                     return Ok(Status::Unstaged);
                 }
-                // Untracked entries are excluded by `UntrackedFiles::None`.
-                StatusItem::IndexWorktree(IndexWorktreeItem::DirectoryContents { .. }) => {}
+                // Only emitted when untracked files are enabled. Unstaged changes
+                // short-circuit above, so untracked is the highest status this
+                // loop can settle on, and the scan continues in search of one.
+                StatusItem::IndexWorktree(IndexWorktreeItem::DirectoryContents {
+                    entry, ..
+                }) => {
+                    if entry.status == DirEntryStatus::Untracked {
+                        status = Status::Untracked;
+                    }
+                }
             }
         }
 
+        // An interrupted scan ends early without an error, so whatever it found
+        // so far may understate the real status. A scan that ran to its end
+        // stands even if the flag was raised since.
+        let paths = self.repo.index_or_empty()?.entries().len();
+        if !finished(&mut iter, paths) {
+            return Ok(Status::Unknown);
+        }
+
         Ok(status)
     }
 
+    /// The number of changed paths in each category. Unlike
+    /// [`branch_status`](Self::branch_status), this always walks the whole
+    /// worktree. Untracked files are only counted when enabled with
+    /// [`untracked_files`](Self::untracked_files).
+    ///
+    /// Returns `None` if the scan is cancelled through
+    /// [`should_interrupt`](Self::should_interrupt).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be read, or the status iterator
+    /// cannot be created or yields an error while iterating.
+    pub fn changes(&self) -> Result<Option<Changes>, Error> {
+        // Count each conflicted path once from the index, like `git status`,
+        // rather than once per stage, and keep them out of the other counts.
+        let index = self.repo.index_or_empty()?;
+        let conflicted: HashSet<_> = index
+            .entries()
+            .iter()
+            .filter(|entry| entry.stage_raw() != 0)
+            .map(|entry| entry.path(&index))
+            .collect();
+        let mut changes = Changes {
+            conflicted: conflicted.len(),
+            ..Changes::default()
+        };
+
+        // Files rather than Collapsed, so an untracked directory counts each
+        // file inside it.
+        let mut iter = self.status_iter(UntrackedFiles::Files)?;
+        for item in &mut iter {
+            let item = match item {
+                Ok(item) => item,
+                Err(_) if self.is_interrupted() => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            if conflicted.contains(item.location()) {
+                continue;
+            }
+            match item {
+                StatusItem::TreeIndex(_) => changes.staged += 1,
+                StatusItem::IndexWorktree(IndexWorktreeItem::Modification {
+                    status: entry,
+                    ..
+                }) => match entry {
+                    EntryStatus::Change(Change::Removed) => changes.deleted += 1,
+                    EntryStatus::Change(_) => changes.unstaged += 1,
+                    // Conflicts are counted from the index above.
+                    EntryStatus::Conflict { .. }
+                    | EntryStatus::NeedsUpdate(_)
+                    | EntryStatus::IntentToAdd => {}
+                },
+                StatusItem::IndexWorktree(IndexWorktreeItem::Rewrite { .. }) => {
+                    debug_assert!(
+                        false,
+                        "index_worktree_rewrites(None) is set; a Rewrite item should never be emitted"
+                    );
+                    changes.unstaged += 1;
+                }
+                StatusItem::IndexWorktree(IndexWorktreeItem::DirectoryContents {
+                    entry, ..
+                }) => {
+                    if entry.status == DirEntryStatus::Untracked {
+                        changes.untracked += 1;
+                    }
+                }
+            }
+        }
+
+        let stages = index
+            .entries()
+            .iter()
+            .filter(|entry| entry.stage_raw() != 0)
+            .count();
+        let paths = index.entries().len() - stages + conflicted.len();
+        if !finished(&mut iter, paths) {
+            return Ok(None);
+        }
+
+        Ok(Some(changes))
+    }
+
+    /// Every changed, unmerged and untracked path, with the modes and ids
+    /// `git status --porcelain=v2` reports. Untracked files are listed like
+    /// `git status` does, following `status.showUntrackedFiles` regardless of
+    /// [`untracked_files`](Self::untracked_files).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be read, or the status iterator
+    /// cannot be created or yields an error while iterating.
+    pub fn porcelain_entries(&self) -> Result<Vec<Entry>, Error> {
+        // Unmerged paths are listed from the index with all their stages, and
+        // kept out of the other changes.
+        let index = self.repo.index_or_empty()?;
+        let unmerged = unmerged_stages(&index);
+
+        let file_mode = self
+            .repo
+            .config_snapshot()
+            .boolean("core.fileMode")
+            .unwrap_or(true);
+        let repo = self.clone().untracked_files(true);
+        // gix collapses a directory whose tracked files are all deleted, while
+        // git lists the untracked files in it, so the files are collapsed here.
+        let shown = self.shown_untracked_files();
+        let collapse = shown == UntrackedFiles::Collapsed;
+
+        // A path can be reported twice, once for HEAD <-> index and once for
+        // index <-> worktree, in any order.
+        let mut staged: HashMap<BString, StagedChange> = HashMap::new();
+        let mut unstaged: HashMap<BString, (char, Option<Object>)> = HashMap::new();
+        let mut untracked_paths = Vec::new();
+        let mode = if collapse {
+            UntrackedFiles::Files
+        } else {
+            shown
+        };
+        for item in repo.status_iter(mode)? {
+            let item = item?;
+            if unmerged.contains_key(item.location()) {
+                continue;
+            }
+            match item {
+                StatusItem::TreeIndex(change) => {
+                    if let Some((path, change)) = staged_change(change) {
+                        staged.insert(path, change);
+                    }
+                }
+                StatusItem::IndexWorktree(IndexWorktreeItem::Modification {
+                    rela_path,
+                    entry,
+                    status,
+                    ..
+                }) => {
+                    let kind = match status {
+                        EntryStatus::Change(Change::Removed) => 'D',
+                        EntryStatus::Change(Change::Type { .. }) => 'T',
+                        EntryStatus::Change(_) => 'M',
+                        EntryStatus::IntentToAdd => 'A',
+                        EntryStatus::Conflict { .. } | EntryStatus::NeedsUpdate(_) => continue,
+                    };
+                    // An intent-to-add entry holds no object yet.
+                    let index = (kind != 'A').then(|| object(entry.mode, &entry.id));
+                    unstaged.insert(rela_path, (kind, index));
+                }
+                StatusItem::IndexWorktree(IndexWorktreeItem::DirectoryContents {
+                    entry, ..
+                }) => untracked_paths.extend(untracked_path(entry)),
+                StatusItem::IndexWorktree(IndexWorktreeItem::Rewrite { .. }) => {}
+            }
+        }
+
+        let workdir = self.repo.workdir().unwrap_or_else(|| Path::new(""));
+        let paths: BTreeSet<BString> = staged.keys().chain(unstaged.keys()).cloned().collect();
+        let changed = paths.into_iter().map(|path| {
+            let (unstaged, unstaged_index) = unstaged.remove(&path).unwrap_or(('.', None));
+            let (staged, head, index) = staged
+                .remove(&path)
+                .unwrap_or_else(|| ('.', unstaged_index.clone(), unstaged_index));
+            let worktree_mode = match unstaged {
+                'D' => 0,
+                '.' => index.as_ref().map_or(0, |index| index.mode),
+                _ => worktree_mode(&workdir.join(from_bstr(&path)), index.as_ref(), file_mode),
+            };
+            Entry::Changed {
+                path: path.into(),
+                staged,
+                unstaged,
+                head,
+                index,
+                worktree_mode,
+            }
+        });
+        let unmerged = unmerged.into_iter().map(|(path, stages)| {
+            let ours = stages[1].clone();
+            Entry::Unmerged {
+                worktree_mode: worktree_mode(
+                    &workdir.join(from_bstr(&path)),
+                    ours.as_ref(),
+                    file_mode,
+                ),
+                path: path.into(),
+                stages,
+            }
+        });
+        if collapse {
+            untracked_paths = collapse_untracked(&index, untracked_paths);
+        }
+        let untracked = untracked_paths
+            .into_iter()
+            .map(|path| Entry::Untracked { path: path.into() });
+        Ok(changed.chain(unmerged).chain(untracked).collect())
+    }
+
+    /// How `git status` lists untracked files, from `status.showUntrackedFiles`.
+    /// Like git, a boolean is accepted too: false hides untracked files, and
+    /// true lists them as `normal` does.
+    fn shown_untracked_files(&self) -> UntrackedFiles {
+        const KEY: &str = "status.showUntrackedFiles";
+        let config = self.repo.config_snapshot();
+        match config.string(KEY).as_deref().map(|value| value.as_bytes()) {
+            None | Some(b"normal") => UntrackedFiles::Collapsed,
+            Some(b"no") => UntrackedFiles::None,
+            Some(b"all") => UntrackedFiles::Files,
+            Some(_) if config.boolean(KEY) == Some(false) => UntrackedFiles::None,
+            Some(_) => UntrackedFiles::Collapsed,
+        }
+    }
+
+    /// Which renames `git status` detects, as set by `status.renames`, or else
+    /// `diff.renames`. Like git, renames are detected when neither is set.
+    #[must_use]
+    pub fn renames(&self) -> Renames {
+        let config = self.repo.config_snapshot();
+        ["status.renames", "diff.renames"]
+            .into_iter()
+            .find_map(|key| {
+                let value = config.string(key)?;
+                let copies = [b"copies".as_slice(), b"copy"]
+                    .iter()
+                    .any(|copies| value.eq_ignore_ascii_case(copies));
+                Some(if copies {
+                    Renames::Copies
+                } else if config.boolean(key) == Some(false) {
+                    Renames::Off
+                } else {
+                    Renames::Renames
+                })
+            })
+            .unwrap_or(Renames::Renames)
+    }
+
+    /// The configured upstream of the current branch, e.g. `origin/main`,
+    /// whether or not it was fetched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the branch configuration is invalid.
+    pub fn upstream_name(&self) -> Result<Option<String>, Error> {
+        let Some(head) = self.repo.head_ref()? else {
+            return Ok(None);
+        };
+        let tracking = head
+            .remote_tracking_ref_name(Direction::Fetch)
+            .transpose()?;
+        Ok(tracking.map(|name| name.shorten().to_string()))
+    }
+
+    /// Whether bytes above 0x7f in paths are quoted (`core.quotePath`).
+    #[must_use]
+    pub fn quote_path(&self) -> bool {
+        self.repo
+            .config_snapshot()
+            .boolean("core.quotePath")
+            .unwrap_or(true)
+    }
+
+    /// An iterator over HEAD <-> index and index <-> worktree changes, listing
+    /// untracked files as `untracked` if they are enabled.
+    fn status_iter(&self, untracked: UntrackedFiles) -> Result<StatusIter, Error> {
+        let mut platform = self.repo.status(Discard)?;
+        if let Some(flag) = &self.should_interrupt {
+            platform = platform.should_interrupt_owned(Arc::clone(flag));
+        }
+        Ok(platform
+            .untracked_files(if self.shows_untracked_files() {
+                untracked
+            } else {
+                UntrackedFiles::None
+            })
+            // Rename detection (on by default) reads blob contents to compute
+            // similarity, which is pure overhead here: a rename maps to the same
+            // staged/unstaged status as a separate delete and add would.
+            .index_worktree_rewrites(None)
+            .tree_index_track_renames(TrackRenames::Disabled)
+            .into_iter(Vec::<BString>::new())?)
+    }
+
+    /// The upstream of the current branch with its ahead/behind counts.
+    ///
+    /// Returns `None` when HEAD is detached or unborn, when the branch has no
+    /// upstream configured, or when the remote-tracking ref does not exist (e.g.
+    /// the remote has never been fetched).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the branch configuration is invalid, a ref cannot be
+    /// peeled to a commit, or the commit graph cannot be traversed.
+    pub fn upstream(&self) -> Result<Option<Upstream>, Error> {
+        let Some(mut head) = self.repo.head_ref()? else {
+            return Ok(None);
+        };
+        let Some(tracking) = head
+            .remote_tracking_ref_name(Direction::Fetch)
+            .transpose()?
+        else {
+            return Ok(None);
+        };
+        let Some(mut upstream) = self.repo.try_find_reference(tracking.as_ref())? else {
+            return Ok(None);
+        };
+
+        let local = head.peel_to_id()?.detach();
+        let remote = upstream.peel_to_id()?.detach();
+        Ok(Some(Upstream {
+            name: upstream.name().shorten().to_string(),
+            ahead: self.count_exclusive(local, remote)?,
+            behind: self.count_exclusive(remote, local)?,
+        }))
+    }
+
+    /// The number of stash entries, i.e. `git stash list | wc -l`.
+    ///
+    /// Without any stash this costs a single ref lookup. Otherwise every stash
+    /// entry is a line in the reflog of `refs/stash`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `refs/stash` or its reflog cannot be read.
+    pub fn stash_count(&self) -> Result<usize, Error> {
+        let Some(stash) = self.repo.try_find_reference("refs/stash")? else {
+            return Ok(0);
+        };
+        let mut log = stash.log_iter();
+        // A stash ref without a reflog still holds the one entry it points at.
+        Ok(log.all()?.map_or(1, Iterator::count))
+    }
+
+    /// The remote that the upstream of the current branch belongs to.
+    ///
+    /// Returns `None` when HEAD is detached or unborn, or the branch has no
+    /// upstream configured. Unlike [`upstream`](Self::upstream), the
+    /// remote-tracking ref does not need to exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the branch configuration is invalid.
+    pub fn upstream_remote(&self) -> Result<Option<Remote>, Error> {
+        let Some(head) = self.repo.head_ref()? else {
+            return Ok(None);
+        };
+        let (Some(name), Some(merge)) = (
+            head.remote_name(Direction::Fetch),
+            head.remote_ref_name(Direction::Fetch).transpose()?,
+        ) else {
+            return Ok(None);
+        };
+        let url = self
+            .repo
+            .find_remote(name.as_bstr())
+            .ok()
+            .and_then(|remote| {
+                remote
+                    .url(Direction::Fetch)
+                    .map(|url| url.to_bstring().to_string())
+            });
+        Ok(Some(Remote {
+            name: name.as_bstr().to_string(),
+            branch: merge.shorten().to_string(),
+            url,
+        }))
+    }
+
+    /// The id of the commit HEAD points at, or `None` on an unborn branch.
+    #[must_use]
+    pub fn head_id(&self) -> Option<String> {
+        self.repo.head_id().ok().map(|id| id.to_string())
+    }
+
+    /// The number of entries in the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be read.
+    pub fn index_len(&self) -> Result<usize, Error> {
+        Ok(self.repo.index_or_empty()?.entries().len())
+    }
+
+    /// The root of the worktree, or `None` for a bare repository.
+    #[must_use]
+    pub fn workdir(&self) -> Option<&Path> {
+        self.repo.workdir()
+    }
+
+    /// The value of git's `color.ui`, if set.
+    #[must_use]
+    pub fn color_ui(&self) -> Option<String> {
+        self.repo
+            .config_snapshot()
+            .string("color.ui")
+            .map(|value| value.to_string())
+    }
+
+    /// The directories whose changes can affect the output: the worktree, if
+    /// any, and the git and common directories unless they are inside it
+    /// (e.g. for a linked worktree).
+    #[must_use]
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let workdir = self.repo.workdir();
+        let mut paths: Vec<PathBuf> = workdir.map(Path::to_path_buf).into_iter().collect();
+        for dir in [self.repo.git_dir(), self.repo.common_dir()] {
+            let inside = workdir.is_some_and(|workdir| dir.starts_with(workdir));
+            if !inside && !paths.iter().any(|path| path == dir) {
+                paths.push(dir.to_path_buf());
+            }
+        }
+        paths
+    }
+
+    /// Whether a change to `path` cannot affect the status, because it is in
+    /// the worktree, untracked, and ignored itself or through a parent
+    /// directory (e.g. `target/` in a Rust project). A path in the git
+    /// directory, or one whose fate cannot be decided, is never ignored.
+    #[must_use]
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let Some(workdir) = self.repo.workdir() else {
+            return false;
+        };
+        let in_git_dir = [self.repo.git_dir(), self.repo.common_dir()]
+            .iter()
+            .any(|dir| path.starts_with(dir));
+        let relative = match path.strip_prefix(workdir) {
+            Ok(relative) if !in_git_dir && !relative.as_os_str().is_empty() => relative,
+            _ => return false,
+        };
+        let Ok(index) = self.repo.index_or_empty() else {
+            return false;
+        };
+        // A tracked file shows up in the status even when it is ignored, and
+        // so does one in a tracked directory.
+        let name = into_bstr(relative);
+        let mut dir = name.clone().into_owned();
+        dir.push(b'/');
+        let tracked = index.entry_by_path(name.as_ref()).is_some()
+            || index
+                .prefixed_entries(dir.as_bstr())
+                .is_some_and(|entries| !entries.is_empty());
+        if tracked {
+            return false;
+        }
+        let Ok(mut excludes) = self.repo.excludes(
+            &index,
+            None,
+            IgnoreSource::WorktreeThenIdMappingIfNotSkipped,
+        ) else {
+            return false;
+        };
+        // Like git, nothing below an ignored directory can be re-included.
+        let mut ancestors: Vec<&Path> = relative.ancestors().collect();
+        ancestors.pop();
+        ancestors.into_iter().rev().any(|ancestor| {
+            let is_dir = ancestor != relative || path.is_dir();
+            excludes
+                .at_path(ancestor, is_dir.then_some(EntryMode::DIR))
+                .is_ok_and(|platform| platform.is_excluded())
+        })
+    }
+
+    /// The number of commits reachable from `tip` but not from `hidden`, i.e.
+    /// the commits on `tip`'s side of their merge base (`git rev-list --count
+    /// hidden..tip`).
+    fn count_exclusive(&self, tip: gix::ObjectId, hidden: gix::ObjectId) -> Result<usize, Error> {
+        if tip == hidden {
+            return Ok(0);
+        }
+        let mut count = 0;
+        for info in self.repo.rev_walk([tip]).with_hidden([hidden]).all()? {
+            info?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Whether the flag set with [`should_interrupt`](Self::should_interrupt)
+    /// has been raised.
+    fn is_interrupted(&self) -> bool {
+        self.should_interrupt
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::SeqCst))
+    }
+
+    /// Whether untracked files are enabled and not turned off by
+    /// `status.showUntrackedFiles` in the git config.
+    fn shows_untracked_files(&self) -> bool {
+        self.untracked_files && self.shown_untracked_files() != UntrackedFiles::None
+    }
+
     /// Whether the index has any unmerged entries, i.e. a conflict is in
     /// progress. Unmerged entries carry a non-zero stage (base/ours/theirs).
     fn has_conflicts(&self) -> Result<bool, Error> {
-        let index = self.0.index_or_empty()?;
+        let index = self.repo.index_or_empty()?;
         Ok(index.entries().iter().any(|entry| entry.stage_raw() != 0))
     }
 
@@ -170,7 +779,7 @@ impl Repository {
     /// `head-name` file, so we read whichever is present instead of inferring the
     /// directory from the repository state.
     fn rebase_head_name(&self) -> Option<String> {
-        let git_dir = self.0.path();
+        let git_dir = self.repo.path();
         for dir in ["rebase-merge", "rebase-apply"] {
             let path = git_dir.join(dir).join("head-name");
             if let Ok(content) = fs::read_to_string(&path) {
@@ -189,8 +798,9 @@ impl Repository {
     /// internals (annotated tags before lightweight, then reverse-alphabetical
     /// by name). This matches `git describe --exact-match` behaviour, which
     /// also does not guarantee a stable winner among ties.
-    fn tag_name(&self) -> Option<String> {
-        let commit = self.0.head_commit().ok()?;
+    #[must_use]
+    pub fn tag_name(&self) -> Option<String> {
+        let commit = self.repo.head_commit().ok()?;
         let format = commit
             .describe()
             .names(SelectRef::AllTags)
@@ -202,7 +812,7 @@ impl Repository {
 
     /// The abbreviated hex of an object id, or `None` if it cannot be resolved.
     fn short_id(&self, id: gix::ObjectId) -> Option<String> {
-        let object = self.0.find_object(id).ok()?;
+        let object = self.repo.find_object(id).ok()?;
         let short = object.id().shorten().ok()?;
         Some(short.to_string())
     }
@@ -210,7 +820,7 @@ impl Repository {
     /// Resolve a full ref name to its shorthand, prettifying via the ref store
     /// when possible and otherwise stripping the well-known namespace prefix.
     fn shorthand_of_ref(&self, refname: &str) -> String {
-        if let Ok(reference) = self.0.find_reference(refname) {
+        if let Ok(reference) = self.repo.find_reference(refname) {
             return reference.name().shorten().to_string();
         }
         // Ref not found (e.g. branch deleted mid-rebase): strip the namespace
@@ -224,6 +834,168 @@ impl Repository {
     }
 }
 
+/// Whether `iter` ran to its end, as opposed to being cut short by the
+/// interrupt flag, which gix does without an error: the iterator then has no
+/// outcome, or not every tracked file was checked. Each of the `paths` in the
+/// index is checked once, however many conflict stages it has.
+fn finished(iter: &mut StatusIter, paths: usize) -> bool {
+    iter.outcome_mut().is_some_and(|outcome| {
+        outcome
+            .index_worktree
+            .tracked_file_modification
+            .entries_processed
+            >= paths
+    })
+}
+
+/// `paths` with every file in a directory that holds no index entries
+/// replaced by the outermost such directory, like `git status` lists them.
+fn collapse_untracked(index: &IndexFile, paths: Vec<BString>) -> Vec<BString> {
+    let mut tracked_dirs = HashSet::new();
+    for entry in index.entries() {
+        let path: &[u8] = entry.path(index);
+        for (end, _) in path.iter().enumerate().filter(|&(_, &byte)| byte == b'/') {
+            tracked_dirs.insert(&path[..=end]);
+        }
+    }
+    let collapsed: BTreeSet<BString> = paths
+        .into_iter()
+        .map(|path| {
+            path.iter()
+                .enumerate()
+                .filter(|&(end, &byte)| byte == b'/' && end + 1 < path.len())
+                .map(|(end, _)| &path[..=end])
+                .find(|dir| !tracked_dirs.contains(dir))
+                .map_or_else(|| path.clone(), BString::from)
+        })
+        .collect();
+    collapsed.into_iter().collect()
+}
+
+/// The path of an untracked dir walk entry, with a trailing `/` for
+/// directories.
+fn untracked_path(entry: DirEntry) -> Option<BString> {
+    if entry.status != DirEntryStatus::Untracked {
+        return None;
+    }
+    let mut path = entry.rela_path;
+    if matches!(
+        entry.disk_kind,
+        Some(DirEntryKind::Directory | DirEntryKind::Repository)
+    ) {
+        path.push(b'/');
+    }
+    Some(path)
+}
+
+/// A status letter with the objects in HEAD and in the index.
+type StagedChange = (char, Option<Object>, Option<Object>);
+
+/// The stages of every unmerged path in `index`, as base, ours and theirs.
+fn unmerged_stages(index: &IndexFile) -> BTreeMap<BString, [Option<Object>; 3]> {
+    let mut unmerged: BTreeMap<BString, [Option<Object>; 3]> = BTreeMap::new();
+    for entry in index.entries() {
+        let stage = match entry.stage_raw() {
+            0 => continue,
+            stage => stage - 1,
+        };
+        let stages = unmerged.entry(entry.path(index).to_owned()).or_default();
+        if let Some(slot) = usize::try_from(stage)
+            .ok()
+            .and_then(|stage| stages.get_mut(stage))
+        {
+            *slot = Some(object(entry.mode, &entry.id));
+        }
+    }
+    unmerged
+}
+
+/// The path of a HEAD <-> index change, with its status letter and its
+/// objects in HEAD and in the index.
+fn staged_change(change: IndexChange) -> Option<(BString, StagedChange)> {
+    let (path, kind, head, index) = match change {
+        IndexChange::Addition {
+            location,
+            entry_mode,
+            id,
+            ..
+        } => (location, 'A', None, Some(object(entry_mode, &id))),
+        IndexChange::Deletion {
+            location,
+            entry_mode,
+            id,
+            ..
+        } => (location, 'D', Some(object(entry_mode, &id)), None),
+        IndexChange::Modification {
+            location,
+            previous_entry_mode,
+            previous_id,
+            entry_mode,
+            id,
+            ..
+        } => {
+            let kind = if previous_entry_mode.bits() & 0o170_000 == entry_mode.bits() & 0o170_000 {
+                'M'
+            } else {
+                'T'
+            };
+            let head = object(previous_entry_mode, &previous_id);
+            (location, kind, Some(head), Some(object(entry_mode, &id)))
+        }
+        // Rename tracking is disabled in `status_iter`.
+        IndexChange::Rewrite { .. } => return None,
+    };
+    Some((path.into_owned(), (kind, head, index)))
+}
+
+/// The mode and id of an index or tree entry.
+fn object(mode: EntryMode, id: &gix::oid) -> Object {
+    Object {
+        mode: mode.bits(),
+        id: id.to_string(),
+    }
+}
+
+/// The mode of `path` in the worktree, derived from `lstat` like git does, or
+/// 0 if it is missing. Without `core.fileMode`, a regular file keeps the mode
+/// of its `index` entry.
+fn worktree_mode(path: &Path, index: Option<&Object>, file_mode: bool) -> u32 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        0o120_000
+    } else if file_type.is_dir() {
+        0o160_000
+    } else if !file_mode {
+        index
+            .map(|index| index.mode)
+            .filter(|mode| mode & 0o170_000 == 0o100_000)
+            .unwrap_or(0o100_644)
+    } else if is_executable(&metadata) {
+        0o100_755
+    } else {
+        0o100_644
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    metadata.permissions().mode() & 0o100 != 0
+}
+
+#[cfg(not(unix))]
+const fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// The number stored in a state file such as `rebase-merge/msgnum`, or `None`
+/// if it is missing or malformed.
+fn read_number(path: &Path) -> Option<usize> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
 /// Extension methods on [`gix::state::InProgress`] for display purposes.
 trait InProgressExt {
     /// A short, human-readable label for the in-progress action (e.g. `"rebase-i"`).
@@ -260,40 +1032,12 @@ impl InProgressExt for InProgress {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::{git, git_stdout};
 
     use anyhow::Result;
-    use assert_cmd::Command;
     use assert_fs::TempDir;
     use assert_fs::prelude::*;
 
-    /// Run `git` in `dir`, neutralizing the user's global signing config and
-    /// pinning identity so fixtures are hermetic.
-    fn git(dir: &Path, args: &[&str]) {
-        let mut full = vec!["-c", "commit.gpgsign=false", "-c", "tag.gpgsign=false"];
-        full.extend_from_slice(args);
-        Command::new("git")
-            .current_dir(dir)
-            .args(&full)
-            .env("GIT_AUTHOR_NAME", "tester")
-            .env("GIT_AUTHOR_EMAIL", "tester@example.com")
-            .env("GIT_COMMITTER_NAME", "tester")
-            .env("GIT_COMMITTER_EMAIL", "tester@example.com")
-            .assert()
-            .success();
-    }
-
-    fn git_stdout(dir: &Path, args: &[&str]) -> Result<String> {
-        let mut full = vec!["-c", "commit.gpgsign=false", "-c", "tag.gpgsign=false"];
-        full.extend_from_slice(args);
-        let cmd = Command::new("git")
-            .current_dir(dir)
-            .args(&full)
-            .assert()
-            .success();
-        let out = cmd.get_output().to_owned();
-        Ok(String::from_utf8(out.stdout)?.trim().to_string())
-    }
-
     /// A repository with a single committed file on `main`.
     fn init_repo() -> Result<TempDir> {
         let dir = TempDir::new()?;
@@ -308,6 +1052,24 @@ mod tests {
         Repository::discover(dir.path()).map_err(Into::into)
     }
 
+    /// Configure `origin/main` as the upstream of `main` without a real remote,
+    /// pointing the remote-tracking ref at the current HEAD.
+    fn track_origin_main(dir: &TempDir) {
+        git(
+            dir.path(),
+            &["remote", "add", "origin", "https://example.com/repo.git"],
+        );
+        git(dir.path(), &["config", "branch.main.remote", "origin"]);
+        git(
+            dir.path(),
+            &["config", "branch.main.merge", "refs/heads/main"],
+        );
+        git(
+            dir.path(),
+            &["update-ref", "refs/remotes/origin/main", "HEAD"],
+        );
+    }
+
     #[test]
     fn branch_name_returns_branch_on_unborn_branch() -> Result<()> {
         let dir = TempDir::new()?;
@@ -332,6 +1094,36 @@ mod tests {
         dir.close().map_err(Into::into)
     }
 
+    #[test]
+    fn action_is_none_without_operation_in_progress() -> Result<()> {
+        let dir = init_repo()?;
+        assert_eq!(open(&dir)?.action(), None);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn head_kind_is_symbolic_on_normal_branch() -> Result<()> {
+        let dir = init_repo()?;
+        assert_eq!(open(&dir)?.head_kind()?, HeadKind::Symbolic);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn head_kind_is_unborn_on_unborn_branch() -> Result<()> {
+        let dir = TempDir::new()?;
+        git(dir.path(), &["init", "-q", "-b", "main"]);
+        assert_eq!(open(&dir)?.head_kind()?, HeadKind::Unborn);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn head_kind_is_detached_on_detached_head() -> Result<()> {
+        let dir = init_repo()?;
+        git(dir.path(), &["checkout", "-q", "--detach", "HEAD"]);
+        assert_eq!(open(&dir)?.head_kind()?, HeadKind::Detached);
+        dir.close().map_err(Into::into)
+    }
+
     #[test]
     fn branch_name_returns_lightweight_tag_on_detached_head_at_tag() -> Result<()> {
         let dir = init_repo()?;
@@ -350,6 +1142,90 @@ mod tests {
         dir.close().map_err(Into::into)
     }
 
+    #[test]
+    fn progress_reads_msgnum_and_end_during_merge_backend_rebase() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child(".git/rebase-merge/head-name")
+            .write_str("refs/heads/feature\n")?;
+        dir.child(".git/rebase-merge/interactive").touch()?;
+        dir.child(".git/rebase-merge/msgnum").write_str("3\n")?;
+        dir.child(".git/rebase-merge/end").write_str("12\n")?;
+        let expected = Progress {
+            current: 3,
+            total: 12,
+        };
+        assert_eq!(open(&dir)?.progress(), Some(expected));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn progress_reads_next_and_last_during_apply_backend_rebase() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child(".git/rebase-apply/head-name")
+            .write_str("refs/heads/feature\n")?;
+        dir.child(".git/rebase-apply/rebasing").touch()?;
+        dir.child(".git/rebase-apply/next").write_str("2\n")?;
+        dir.child(".git/rebase-apply/last").write_str("5\n")?;
+        let expected = Progress {
+            current: 2,
+            total: 5,
+        };
+        assert_eq!(open(&dir)?.progress(), Some(expected));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn progress_reads_next_and_last_during_am() -> Result<()> {
+        let dir = init_repo()?;
+        // gix detects ApplyMailbox from `applying`, which `git am` creates.
+        dir.child(".git/rebase-apply/applying").touch()?;
+        dir.child(".git/rebase-apply/next").write_str("1\n")?;
+        dir.child(".git/rebase-apply/last").write_str("3\n")?;
+        let repo = open(&dir)?;
+        assert_eq!(repo.action().as_deref(), Some("am"));
+        let expected = Progress {
+            current: 1,
+            total: 3,
+        };
+        assert_eq!(repo.progress(), Some(expected));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn progress_is_none_when_files_are_missing_or_malformed() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child(".git/rebase-merge/interactive").touch()?;
+        dir.child(".git/rebase-merge/msgnum").write_str("three\n")?;
+        dir.child(".git/rebase-merge/end").write_str("12\n")?;
+        assert_eq!(open(&dir)?.progress(), None);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn progress_is_none_without_rebase() -> Result<()> {
+        let dir = init_repo()?;
+        let head = git_stdout(dir.path(), &["rev-parse", "HEAD"])?;
+        dir.child(".git/MERGE_HEAD")
+            .write_str(&format!("{head}\n"))?;
+        assert_eq!(open(&dir)?.progress(), None);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn action_is_merge_during_merge() -> Result<()> {
+        let dir = init_repo()?;
+        // gix reports the `Merge` state when MERGE_HEAD exists. Fabricate it
+        // directly rather than via `git merge`, whose conflict behavior is
+        // environment-sensitive (e.g. it can no-op on some CI runners).
+        let head = git_stdout(dir.path(), &["rev-parse", "HEAD"])?;
+        dir.child(".git/MERGE_HEAD")
+            .write_str(&format!("{head}\n"))?;
+        let repo = open(&dir)?;
+        assert_eq!(repo.branch()?, "main");
+        assert_eq!(repo.action().as_deref(), Some("merge"));
+        dir.close().map_err(Into::into)
+    }
+
     #[test]
     fn branch_name_appends_merge_action_during_merge() -> Result<()> {
         let dir = init_repo()?;
@@ -373,6 +1249,18 @@ mod tests {
         dir.close().map_err(Into::into)
     }
 
+    #[test]
+    fn branch_omits_action_during_interactive_rebase() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child(".git/rebase-merge/head-name")
+            .write_str("refs/heads/feature\n")?;
+        dir.child(".git/rebase-merge/interactive").touch()?;
+        let repo = open(&dir)?;
+        assert_eq!(repo.branch()?, "feature");
+        assert_eq!(repo.action().as_deref(), Some("rebase-i"));
+        dir.close().map_err(Into::into)
+    }
+
     #[test]
     fn branch_name_uses_rebase_apply_head_name_during_apply_rebase() -> Result<()> {
         let dir = init_repo()?;
@@ -525,6 +1413,175 @@ mod tests {
         dir.close().map_err(Into::into)
     }
 
+    #[test]
+    fn branch_status_is_untracked_on_untracked_file_when_enabled() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child("untracked").write_str("x\n")?;
+        let repo = open(&dir)?.untracked_files(true);
+        assert_eq!(repo.branch_status()?, Status::Untracked);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn branch_status_prefers_untracked_over_staged_when_both_present() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child("g").write_str("b\n")?;
+        git(dir.path(), &["add", "g"]);
+        dir.child("untracked").write_str("x\n")?;
+        let repo = open(&dir)?.untracked_files(true);
+        assert_eq!(repo.branch_status()?, Status::Untracked);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn branch_status_prefers_unstaged_over_untracked_when_both_present() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child("untracked/nested").write_str("x\n")?;
+        dir.child("f").write_str("changed\n")?;
+        let repo = open(&dir)?.untracked_files(true);
+        assert_eq!(repo.branch_status()?, Status::Unstaged);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn branch_status_ignores_gitignored_files_when_untracked_enabled() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child(".gitignore").write_str("ignored\n")?;
+        git(dir.path(), &["add", ".gitignore"]);
+        git(dir.path(), &["commit", "-qm", "ignore"]);
+        dir.child("ignored").write_str("x\n")?;
+        let repo = open(&dir)?.untracked_files(true);
+        assert_eq!(repo.branch_status()?, Status::NotChanged);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn branch_status_respects_show_untracked_files_no() -> Result<()> {
+        let dir = init_repo()?;
+        git(dir.path(), &["config", "status.showUntrackedFiles", "no"]);
+        dir.child("untracked").write_str("x\n")?;
+        let repo = open(&dir)?.untracked_files(true);
+        assert_eq!(repo.branch_status()?, Status::NotChanged);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn branch_status_respects_show_untracked_files_false() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child("untracked").write_str("x\n")?;
+        for value in ["false", "Off", "0"] {
+            git(dir.path(), &["config", "status.showUntrackedFiles", value]);
+            let repo = open(&dir)?.untracked_files(true);
+            assert_eq!(repo.branch_status()?, Status::NotChanged, "{value}");
+        }
+        git(dir.path(), &["config", "status.showUntrackedFiles", "true"]);
+        let repo = open(&dir)?.untracked_files(true);
+        assert_eq!(repo.branch_status()?, Status::Untracked);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn color_ui_reads_repository_config() -> Result<()> {
+        let dir = init_repo()?;
+        git(dir.path(), &["config", "color.ui", "never"]);
+        let repo = open(&dir)?;
+        assert_eq!(repo.color_ui().as_deref(), Some("never"));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn paths_is_the_worktree_for_a_plain_repository() -> Result<()> {
+        let dir = init_repo()?;
+        let repo = open(&dir)?;
+        let workdir = dir.path().canonicalize()?;
+        let paths: Vec<_> = repo
+            .paths()
+            .iter()
+            .map(|path| path.canonicalize())
+            .collect::<Result<_, _>>()?;
+        assert_eq!(paths, [workdir]);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn paths_includes_git_dirs_of_a_linked_worktree() -> Result<()> {
+        let dir = init_repo()?;
+        let linked = TempDir::new()?;
+        let worktree = linked.path().join("w");
+        git(
+            dir.path(),
+            &["worktree", "add", "-q", &worktree.to_string_lossy()],
+        );
+        let repo = Repository::discover(&worktree)?;
+        let git_dir = dir.path().canonicalize()?.join(".git");
+        let paths: Vec<_> = repo
+            .paths()
+            .iter()
+            .map(|path| path.canonicalize())
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            paths,
+            [
+                worktree.canonicalize()?,
+                git_dir.join("worktrees").join("w"),
+                git_dir
+            ]
+        );
+        linked.close()?;
+        dir.close().map_err(Into::into)
+    }
+
+    /// A repository ignoring `target/` and `*.log`, with a tracked
+    /// `target/keep` and a `target/out` that is only ignored.
+    fn init_ignoring_repo() -> Result<(TempDir, Repository, PathBuf)> {
+        let dir = init_repo()?;
+        dir.child(".gitignore").write_str("target/\n*.log\n")?;
+        dir.child("target/keep").write_str("a\n")?;
+        dir.child("target/out").write_str("a\n")?;
+        git(dir.path(), &["add", ".gitignore"]);
+        git(dir.path(), &["add", "-f", "target/keep"]);
+        let repo = open(&dir)?;
+        let workdir = repo.paths().into_iter().next().unwrap_or_default();
+        Ok((dir, repo, workdir))
+    }
+
+    #[test]
+    fn is_ignored_for_files_in_an_ignored_directory() -> Result<()> {
+        let (dir, repo, workdir) = init_ignoring_repo()?;
+        assert!(repo.is_ignored(&workdir.join("target/out")));
+        assert!(repo.is_ignored(&workdir.join("target/debug/new")));
+        assert!(repo.is_ignored(&workdir.join("build.log")));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn is_ignored_is_false_for_tracked_files() -> Result<()> {
+        let (dir, repo, workdir) = init_ignoring_repo()?;
+        assert!(!repo.is_ignored(&workdir.join("f")));
+        assert!(!repo.is_ignored(&workdir.join("target/keep")));
+        // It is ignored, but the tracked file in it is not.
+        assert!(!repo.is_ignored(&workdir.join("target")));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn is_ignored_is_false_for_untracked_files() -> Result<()> {
+        let (dir, repo, workdir) = init_ignoring_repo()?;
+        assert!(!repo.is_ignored(&workdir.join("new")));
+        assert!(!repo.is_ignored(&workdir.join("src/new")));
+        assert!(!repo.is_ignored(&workdir.join(".gitignore")));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn is_ignored_is_false_in_the_git_dir() -> Result<()> {
+        let (dir, repo, workdir) = init_ignoring_repo()?;
+        dir.child(".git/info/exclude").write_str("*\n")?;
+        assert!(!repo.is_ignored(&workdir.join(".git/index")));
+        assert!(!repo.is_ignored(&workdir));
+        dir.close().map_err(Into::into)
+    }
+
     #[test]
     fn branch_status_is_conflicted_on_merge_conflict() -> Result<()> {
         let dir = init_repo()?;
@@ -546,4 +1603,226 @@ mod tests {
         assert_eq!(open(&dir)?.branch_status()?, Status::Conflicted);
         dir.close().map_err(Into::into)
     }
+
+    #[test]
+    fn changes_are_empty_on_clean_tree() -> Result<()> {
+        let dir = init_repo()?;
+        assert_eq!(open(&dir)?.changes()?, Some(Changes::default()));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn changes_count_each_category() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child("g").write_str("g\n")?;
+        dir.child("h").write_str("h\n")?;
+        git(dir.path(), &["add", "g", "h"]);
+        git(dir.path(), &["commit", "-qm", "more"]);
+        // Two staged, one unstaged, one deleted and two untracked files (one
+        // in an untracked directory).
+        dir.child("new").write_str("x\n")?;
+        dir.child("f").write_str("staged\n")?;
+        git(dir.path(), &["add", "new", "f"]);
+        dir.child("g").write_str("unstaged\n")?;
+        fs::remove_file(dir.child("h").path())?;
+        dir.child("u1").write_str("x\n")?;
+        dir.child("dir/u2").write_str("x\n")?;
+        let repo = open(&dir)?.untracked_files(true);
+        let expected = Changes {
+            staged: 2,
+            unstaged: 1,
+            conflicted: 0,
+            deleted: 1,
+            untracked: 2,
+        };
+        assert_eq!(repo.changes()?, Some(expected));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn changes_ignore_untracked_files_unless_enabled() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child("untracked").write_str("x\n")?;
+        assert_eq!(open(&dir)?.changes()?, Some(Changes::default()));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn changes_count_conflicted_path_once() -> Result<()> {
+        let dir = init_repo()?;
+        git(dir.path(), &["checkout", "-q", "-b", "other"]);
+        dir.child("f").write_str("theirs\n")?;
+        git(dir.path(), &["commit", "-qam", "theirs"]);
+        git(dir.path(), &["checkout", "-q", "main"]);
+        dir.child("f").write_str("ours\n")?;
+        git(dir.path(), &["commit", "-qam", "ours"]);
+        let base = git_stdout(dir.path(), &["merge-base", "main", "other"])?;
+        let base_tree = format!("{base}^{{tree}}");
+        git(
+            dir.path(),
+            &["read-tree", "-m", &base_tree, "main^{tree}", "other^{tree}"],
+        );
+        let expected = Changes {
+            conflicted: 1,
+            ..Changes::default()
+        };
+        assert_eq!(open(&dir)?.changes()?, Some(expected));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn branch_status_is_unknown_when_interrupted() -> Result<()> {
+        let dir = init_repo()?;
+        let repo = open(&dir)?.should_interrupt(Arc::new(AtomicBool::new(true)));
+        assert_eq!(repo.branch_status()?, Status::Unknown);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn branch_status_reports_conflict_even_when_interrupted() -> Result<()> {
+        let dir = init_repo()?;
+        git(dir.path(), &["checkout", "-q", "-b", "other"]);
+        dir.child("f").write_str("theirs\n")?;
+        git(dir.path(), &["commit", "-qam", "theirs"]);
+        git(dir.path(), &["checkout", "-q", "main"]);
+        dir.child("f").write_str("ours\n")?;
+        git(dir.path(), &["commit", "-qam", "ours"]);
+        let base = git_stdout(dir.path(), &["merge-base", "main", "other"])?;
+        let base_tree = format!("{base}^{{tree}}");
+        git(
+            dir.path(),
+            &["read-tree", "-m", &base_tree, "main^{tree}", "other^{tree}"],
+        );
+        // Conflicts are read from the index before the interruptible scan.
+        let repo = open(&dir)?.should_interrupt(Arc::new(AtomicBool::new(true)));
+        assert_eq!(repo.branch_status()?, Status::Conflicted);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn branch_status_is_unaffected_by_unraised_interrupt_flag() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child("f").write_str("changed\n")?;
+        let repo = open(&dir)?.should_interrupt(Arc::new(AtomicBool::new(false)));
+        assert_eq!(repo.branch_status()?, Status::Unstaged);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn scan_finished_before_the_interrupt_stands() -> Result<()> {
+        let dir = init_repo()?;
+        dir.child("f").write_str("b\n")?;
+        let flag = Arc::new(AtomicBool::new(false));
+        let repo = open(&dir)?.should_interrupt(Arc::clone(&flag));
+        let mut iter = repo.status_iter(UntrackedFiles::Collapsed)?;
+        assert_eq!((&mut iter).count(), 1);
+        // The deadline passes after the last item, but before the check.
+        flag.store(true, Ordering::SeqCst);
+        assert!(finished(&mut iter, 1));
+        assert!(!finished(&mut iter, 2));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn changes_are_none_when_interrupted() -> Result<()> {
+        let dir = init_repo()?;
+        let repo = open(&dir)?.should_interrupt(Arc::new(AtomicBool::new(true)));
+        assert_eq!(repo.changes()?, None);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn upstream_is_none_without_tracking_config() -> Result<()> {
+        let dir = init_repo()?;
+        assert_eq!(open(&dir)?.upstream()?, None);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn upstream_is_none_when_tracking_ref_not_fetched() -> Result<()> {
+        let dir = init_repo()?;
+        track_origin_main(&dir);
+        git(
+            dir.path(),
+            &["update-ref", "-d", "refs/remotes/origin/main"],
+        );
+        assert_eq!(open(&dir)?.upstream()?, None);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn upstream_is_none_on_detached_head() -> Result<()> {
+        let dir = init_repo()?;
+        track_origin_main(&dir);
+        git(dir.path(), &["checkout", "-q", "--detach", "HEAD"]);
+        assert_eq!(open(&dir)?.upstream()?, None);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn upstream_is_in_sync_when_tracking_ref_matches_head() -> Result<()> {
+        let dir = init_repo()?;
+        track_origin_main(&dir);
+        let expected = Upstream {
+            name: "origin/main".to_owned(),
+            ahead: 0,
+            behind: 0,
+        };
+        assert_eq!(open(&dir)?.upstream()?, Some(expected));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn upstream_counts_commits_on_each_side_of_merge_base() -> Result<()> {
+        let dir = init_repo()?;
+        track_origin_main(&dir);
+        // Two commits only on the upstream...
+        git(dir.path(), &["checkout", "-q", "-b", "remote-work"]);
+        git(dir.path(), &["commit", "-q", "--allow-empty", "-m", "r1"]);
+        git(dir.path(), &["commit", "-q", "--allow-empty", "-m", "r2"]);
+        git(
+            dir.path(),
+            &["update-ref", "refs/remotes/origin/main", "HEAD"],
+        );
+        // ...and one only on the local branch.
+        git(dir.path(), &["checkout", "-q", "main"]);
+        git(dir.path(), &["commit", "-q", "--allow-empty", "-m", "l1"]);
+        let expected = Upstream {
+            name: "origin/main".to_owned(),
+            ahead: 1,
+            behind: 2,
+        };
+        assert_eq!(open(&dir)?.upstream()?, Some(expected));
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn stash_count_is_zero_without_stash() -> Result<()> {
+        let dir = init_repo()?;
+        assert_eq!(open(&dir)?.stash_count()?, 0);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn stash_count_counts_stash_entries() -> Result<()> {
+        let dir = init_repo()?;
+        for content in ["b\n", "c\n", "d\n"] {
+            dir.child("f").write_str(content)?;
+            git(dir.path(), &["stash", "push", "-q"]);
+        }
+        assert_eq!(open(&dir)?.stash_count()?, 3);
+        dir.close().map_err(Into::into)
+    }
+
+    #[test]
+    fn stash_count_decreases_after_drop() -> Result<()> {
+        let dir = init_repo()?;
+        for content in ["b\n", "c\n"] {
+            dir.child("f").write_str(content)?;
+            git(dir.path(), &["stash", "push", "-q"]);
+        }
+        git(dir.path(), &["stash", "drop", "-q"]);
+        assert_eq!(open(&dir)?.stash_count()?, 1);
+        dir.close().map_err(Into::into)
+    }
 }
@@ -0,0 +1,43 @@
+# git-branch-status integration for bash.
+#
+# Load it from ~/.bashrc, after PS1 is set:
+#
+#     eval "$(git branch-status init bash)"
+#
+# The branch is refreshed from PROMPT_COMMAND and inserted before a trailing
+# `\$ ` in PS1, or appended otherwise. `--mode bash` escapes it for PS1, so a
+# branch name is never run as a command.
+
+# Whether the current directory is inside a git repository, checked without
+# forking so that directories outside repositories cost nothing.
+__git_branch_status_in_repo() {
+  [[ -n ${GIT_DIR-} ]] && return 0
+  local dir=$PWD
+  while true; do
+    [[ -e $dir/.git ]] && return 0
+    [[ -z $dir || $dir == / ]] && return 1
+    dir=${dir%/*}
+  done
+}
+
+__git_branch_status_prompt_command() {
+  local exit=$? branch=
+  if __git_branch_status_in_repo; then
+    branch=$(command git-branch-status --mode bash 2>/dev/null)
+  fi
+  local ps1=$__git_branch_status_ps1
+  if [[ -z $branch ]]; then
+    PS1=$ps1
+  elif [[ $ps1 == *'\$ ' ]]; then
+    PS1="${ps1%'\$ '} $branch"'\$ '
+  else
+    PS1="$ps1$branch "
+  fi
+  return "$exit"
+}
+
+# Keep the original PS1 when this is evaluated again.
+__git_branch_status_ps1=${__git_branch_status_ps1-$PS1}
+if [[ ${PROMPT_COMMAND-} != *__git_branch_status_prompt_command* ]]; then
+  PROMPT_COMMAND="__git_branch_status_prompt_command${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
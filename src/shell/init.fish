@@ -0,0 +1,57 @@
+# git-branch-status integration for fish.
+#
+# Load it from ~/.config/fish/config.fish:
+#
+#     git branch-status init fish | source
+#
+# It defines `git_branch_status` and calls it from `fish_right_prompt`, after
+# any right prompt that was already defined. Arguments to `git_branch_status`
+# are passed on, e.g. `git_branch_status --counts`.
+#
+# Each status is colored from `git_branch_status_color_<status>` when it is
+# set, which takes the same arguments as `set_color` and the `fish_color_*`
+# variables:
+#
+#     set -U git_branch_status_color_staged bryellow --bold
+#
+# The statuses are not_changed, staged, untracked, unstaged, conflicted,
+# unknown and failed.
+
+function git_branch_status --description 'Print the git branch colored by status'
+    set -l colors
+    for state in not_changed staged untracked unstaged conflicted unknown failed
+        set -l var git_branch_status_color_$state
+        if set -q $var
+            set -a colors __git_branch_status_color_$state=(set_color $$var)
+        end
+    end
+    env $colors git-branch-status --mode fish $argv 2>/dev/null
+end
+
+# Whether the current directory is inside a git repository, checked without
+# forking so that directories outside repositories cost nothing.
+function __git_branch_status_in_repo
+    set -q GIT_DIR; and return 0
+    set -l dir $PWD
+    while true
+        test -e $dir/.git; and return 0
+        test "$dir" = /; and return 1
+        set dir (string replace -r '/[^/]*$' '' -- $dir)
+        test -n "$dir"; or set dir /
+    end
+end
+
+# Keep the original right prompt when this is sourced again.
+if not functions -q __git_branch_status_right_prompt
+    if functions -q fish_right_prompt
+        functions -c fish_right_prompt __git_branch_status_right_prompt
+    else
+        function __git_branch_status_right_prompt
+        end
+    end
+end
+
+function fish_right_prompt
+    __git_branch_status_right_prompt
+    __git_branch_status_in_repo; and git_branch_status
+end
@@ -0,0 +1,41 @@
+# git-branch-status integration for PowerShell.
+#
+# Load it from your profile ($PROFILE):
+#
+#     git branch-status init powershell | Out-String | Invoke-Expression
+#
+# It replaces `prompt` with the default `PS <location>> ` prompt, followed by
+# the branch. The branch is only ever used as a value, so `$` and backticks in
+# its name are never expanded.
+
+# Whether the current directory is inside a git repository, checked without
+# starting a process so that directories outside repositories cost nothing.
+function __GitBranchStatusInRepo {
+    if ($env:GIT_DIR) { return $true }
+    $dir = $ExecutionContext.SessionState.Path.CurrentFileSystemLocation.ProviderPath
+    while ($dir) {
+        if (Test-Path -LiteralPath (Join-Path $dir '.git')) { return $true }
+        $dir = Split-Path -Parent $dir
+    }
+    $false
+}
+
+function prompt {
+    $branch = ''
+    if (__GitBranchStatusInRepo) {
+        $lastExitCode = $global:LASTEXITCODE
+        # The output contains ↑ and ↓, so decode it as UTF-8 on every platform.
+        $encoding = [Console]::OutputEncoding
+        try {
+            [Console]::OutputEncoding = [Text.Encoding]::UTF8
+            $branch = (git-branch-status --mode powershell 2>$null) -join ''
+        } finally {
+            [Console]::OutputEncoding = $encoding
+        }
+        $global:LASTEXITCODE = $lastExitCode
+    }
+
+    $location = $ExecutionContext.SessionState.Path.CurrentLocation
+    $branch = if ($branch) { " $branch" } else { '' }
+    "PS $location$branch$('>' * ($NestedPromptLevel + 1)) "
+}
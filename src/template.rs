@@ -0,0 +1,454 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `--format` template language.
+//!
+//! A template is literal text with placeholders and conditional sections:
+//!
+//! - `{branch}`, `{action}`, `{progress}`, `{status}`, `{upstream}`, `{ahead}`, `{behind}`,
+//!   `{stash}`, and with `--counts` also `{staged}`, `{unstaged}`, `{deleted}`,
+//!   `{conflicted}` and `{untracked}`, are replaced with the corresponding
+//!   value, which is empty when there is nothing to show (e.g. `{action}`
+//!   outside a rebase or merge).
+//! - `{branch:red}` paints the value in a color, and `{branch:status}` in the
+//!   color of the current status.
+//! - `[...]` is a conditional section, shown only when at least one placeholder
+//!   inside it is non-empty, e.g. `[:{action}]`.
+//! - `\` makes the next character literal, e.g. `\[` or `\{`.
+//!
+//! Both literal text and values are escaped by the output [`Mode`], so the same
+//! template works for every shell.
+
+use std::iter::Peekable;
+use std::mem;
+use std::str::{Chars, FromStr};
+
+use crate::branch::{Branch, Status};
+use crate::color::{Color, Palette, ParseColorError};
+use crate::mode::Mode;
+
+/// A parsed `--format` template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Node>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Text(String),
+    Placeholder {
+        field: Field,
+        color: Option<ColorSpec>,
+    },
+    Section(Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Branch,
+    Action,
+    Progress,
+    Status,
+    Upstream,
+    Ahead,
+    Behind,
+    Stash,
+    Staged,
+    Unstaged,
+    Deleted,
+    Conflicted,
+    Untracked,
+}
+
+/// The color directive of a placeholder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorSpec {
+    /// The color of the current [`Status`].
+    Status,
+    Fixed(Color),
+}
+
+/// The error returned when a `--format` template is malformed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseTemplateError {
+    #[error("unknown placeholder `{{{0}}}`")]
+    UnknownPlaceholder(String),
+    #[error(transparent)]
+    UnknownColor(#[from] ParseColorError),
+    #[error("unclosed `{{`")]
+    UnclosedPlaceholder,
+    #[error("unmatched `}}`")]
+    UnmatchedBrace,
+    #[error("unclosed `[`")]
+    UnclosedSection,
+    #[error("unmatched `]`")]
+    UnmatchedBracket,
+    #[error("trailing `\\` at the end of the template")]
+    TrailingBackslash,
+}
+
+impl FromStr for Template {
+    type Err = ParseTemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars().peekable();
+        let nodes = parse_nodes(&mut chars, false)?;
+        Ok(Self(nodes))
+    }
+}
+
+/// Parse nodes up to the end of input, or up to the `]` closing the current
+/// section when `in_section` is set.
+fn parse_nodes(
+    chars: &mut Peekable<Chars<'_>>,
+    in_section: bool,
+) -> Result<Vec<Node>, ParseTemplateError> {
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+    loop {
+        let Some(c) = chars.next() else {
+            if in_section {
+                return Err(ParseTemplateError::UnclosedSection);
+            }
+            break;
+        };
+        match c {
+            '\\' => text.push(chars.next().ok_or(ParseTemplateError::TrailingBackslash)?),
+            '{' | '[' => {
+                if !text.is_empty() {
+                    nodes.push(Node::Text(mem::take(&mut text)));
+                }
+                nodes.push(if c == '{' {
+                    parse_placeholder(chars)?
+                } else {
+                    Node::Section(parse_nodes(chars, true)?)
+                });
+            }
+            ']' if in_section => break,
+            ']' => return Err(ParseTemplateError::UnmatchedBracket),
+            '}' => return Err(ParseTemplateError::UnmatchedBrace),
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        nodes.push(Node::Text(text));
+    }
+    Ok(nodes)
+}
+
+/// Parse the inside of `{...}`, after the opening brace.
+fn parse_placeholder(chars: &mut Peekable<Chars<'_>>) -> Result<Node, ParseTemplateError> {
+    let mut inner = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) => inner.push(c),
+            None => return Err(ParseTemplateError::UnclosedPlaceholder),
+        }
+    }
+    let (name, color) = match inner.split_once(':') {
+        Some((name, "status")) => (name, Some(ColorSpec::Status)),
+        Some((name, color)) => (name, Some(ColorSpec::Fixed(color.parse()?))),
+        None => (inner.as_str(), None),
+    };
+    let field = match name {
+        "branch" => Field::Branch,
+        "action" => Field::Action,
+        "progress" => Field::Progress,
+        "status" => Field::Status,
+        "upstream" => Field::Upstream,
+        "ahead" => Field::Ahead,
+        "behind" => Field::Behind,
+        "stash" => Field::Stash,
+        "staged" => Field::Staged,
+        "unstaged" => Field::Unstaged,
+        "deleted" => Field::Deleted,
+        "conflicted" => Field::Conflicted,
+        "untracked" => Field::Untracked,
+        _ => return Err(ParseTemplateError::UnknownPlaceholder(name.to_owned())),
+    };
+    Ok(Node::Placeholder { field, color })
+}
+
+impl Template {
+    /// Render the template for `branch`, escaping for `mode`, and coloring for
+    /// it with `palette` if there is one.
+    #[must_use]
+    pub fn render(&self, mode: &Mode, branch: &Branch, palette: Option<&Palette>) -> String {
+        render_nodes(&self.0, mode, branch, palette).0
+    }
+}
+
+/// Render `nodes`, returning the output and whether any placeholder in them
+/// had a non-empty value.
+fn render_nodes(
+    nodes: &[Node],
+    mode: &Mode,
+    branch: &Branch,
+    palette: Option<&Palette>,
+) -> (String, bool) {
+    let mut out = String::new();
+    let mut filled = false;
+    for node in nodes {
+        match node {
+            Node::Text(text) => out += &mode.escape(text),
+            Node::Placeholder { field, color } => {
+                let value = field.value(branch);
+                if value.is_empty() {
+                    continue;
+                }
+                filled = true;
+                let value = mode.escape(&value);
+                match (color, palette) {
+                    (Some(ColorSpec::Status), Some(palette)) => {
+                        out += &mode.paint_status(palette, &branch.status, &value);
+                    }
+                    (Some(ColorSpec::Fixed(color)), Some(_)) => out += &mode.paint(*color, &value),
+                    (_, None) | (None, _) => out += &value,
+                }
+            }
+            Node::Section(children) => {
+                let (section, section_filled) = render_nodes(children, mode, branch, palette);
+                if section_filled {
+                    out += &section;
+                    filled = true;
+                }
+            }
+        }
+    }
+    (out, filled)
+}
+
+impl Field {
+    /// The value of this field for `branch`, empty when there is nothing to
+    /// show.
+    fn value(self, branch: &Branch) -> String {
+        let upstream = branch.upstream.as_ref();
+        let changes = branch.changes.as_ref();
+        let count = |n: usize| if n > 0 { n.to_string() } else { String::new() };
+        match self {
+            Self::Branch => branch.name.clone(),
+            Self::Action => branch.action.clone().unwrap_or_default(),
+            Self::Progress => branch
+                .progress
+                .as_ref()
+                .map(|p| format!("{}/{}", p.current, p.total))
+                .unwrap_or_default(),
+            Self::Status => match branch.status {
+                Status::NotChanged => String::new(),
+                ref status => status.name().to_owned(),
+            },
+            Self::Upstream => upstream.map(|u| u.name.clone()).unwrap_or_default(),
+            Self::Ahead => upstream.map(|u| count(u.ahead)).unwrap_or_default(),
+            Self::Behind => upstream.map(|u| count(u.behind)).unwrap_or_default(),
+            Self::Stash => count(branch.stash),
+            Self::Staged => changes.map(|c| count(c.staged)).unwrap_or_default(),
+            Self::Unstaged => changes.map(|c| count(c.unstaged)).unwrap_or_default(),
+            Self::Deleted => changes.map(|c| count(c.deleted)).unwrap_or_default(),
+            Self::Conflicted => changes.map(|c| count(c.conflicted)).unwrap_or_default(),
+            Self::Untracked => changes.map(|c| count(c.untracked)).unwrap_or_default(),
+        }
+    }
+}
+
+// Templates share `{...}` placeholders with format strings by design.
+#[cfg(test)]
+#[allow(clippy::literal_string_with_formatting_args)]
+mod tests {
+    use owo_colors::OwoColorize as _;
+
+    use super::*;
+    use crate::branch::{Changes, Progress, Upstream};
+    use crate::test_util::branch;
+
+    fn render(template: &str, mode: &Mode, branch: &Branch) -> String {
+        let template: Template = template.parse().expect("valid template");
+        template.render(mode, branch, Some(&Palette::DEFAULT))
+    }
+
+    #[test]
+    fn test_render_plain_placeholder() {
+        let actual = render("on {branch}!", &Mode::Stdout, &branch("main"));
+        assert_eq!(actual, "on main!");
+    }
+
+    #[test]
+    fn test_render_hides_empty_section() {
+        let actual = render("{branch}[:{action}]", &Mode::Stdout, &branch("main"));
+        assert_eq!(actual, "main");
+    }
+
+    #[test]
+    fn test_render_shows_filled_section() {
+        let branch = Branch {
+            action: Some("rebase-i".to_owned()),
+            ..branch("main")
+        };
+        let actual = render("{branch}[:{action}]", &Mode::Stdout, &branch);
+        assert_eq!(actual, "main:rebase-i");
+    }
+
+    #[test]
+    fn test_render_progress() {
+        let branch = Branch {
+            action: Some("rebase-i".to_owned()),
+            progress: Some(Progress {
+                current: 3,
+                total: 12,
+            }),
+            ..branch("main")
+        };
+        let actual = render("{branch}[|{action}[ {progress}]]", &Mode::Stdout, &branch);
+        assert_eq!(actual, "main|rebase-i 3/12");
+    }
+
+    #[test]
+    fn test_render_nested_sections() {
+        let branch = Branch {
+            upstream: Some(Upstream {
+                name: "origin/main".to_owned(),
+                ahead: 2,
+                behind: 0,
+            }),
+            ..branch("main")
+        };
+        let actual = render("{branch}[ ([↑{ahead}][↓{behind}])]", &Mode::Stdout, &branch);
+        assert_eq!(actual, "main (↑2)");
+    }
+
+    #[test]
+    fn test_render_stash() {
+        let branch = Branch {
+            stash: 4,
+            ..branch("main")
+        };
+        let actual = render("{branch}[ ${stash}]", &Mode::Stdout, &branch);
+        assert_eq!(actual, "main $4");
+    }
+
+    #[test]
+    fn test_render_change_counts() {
+        let branch = Branch {
+            changes: Some(Changes {
+                staged: 3,
+                unstaged: 2,
+                ..Changes::default()
+            }),
+            ..branch("main")
+        };
+        let actual = render(
+            "{branch}[ +{staged}][ ~{unstaged}][ ?{untracked}]",
+            &Mode::Stdout,
+            &branch,
+        );
+        assert_eq!(actual, "main +3 ~2");
+    }
+
+    #[test]
+    fn test_render_status_color() {
+        let branch = Branch {
+            status: Status::Staged,
+            ..branch("main")
+        };
+        let actual = render("{branch:status}", &Mode::Stdout, &branch);
+        assert_eq!(actual, format!("{}", "main".yellow()));
+    }
+
+    #[test]
+    fn test_render_fixed_color_in_zsh() {
+        let actual = render("{branch:blue}", &Mode::Zsh, &branch("main"));
+        assert_eq!(actual, "%F{blue}main%f");
+    }
+
+    #[test]
+    fn test_render_fixed_color_in_bash() {
+        let actual = render("{branch:cyan}", &Mode::Bash, &branch("main"));
+        assert_eq!(actual, r"\[\e[36m\]main\[\e[39m\]");
+    }
+
+    #[test]
+    fn test_render_escapes_literal_text_in_zsh() {
+        let actual = render("100% {branch}", &Mode::Zsh, &branch("main"));
+        assert_eq!(actual, "100%% main");
+    }
+
+    #[test]
+    fn test_render_escapes_literal_text_in_tmux() {
+        let actual = render("#1 {branch:red}", &Mode::Tmux, &branch("main"));
+        assert_eq!(actual, "##1 #[fg=red]main#[default]");
+    }
+
+    #[test]
+    fn test_render_escapes_literal_text_in_tcsh() {
+        let actual = render("100%! {branch}", &Mode::Tcsh, &branch("main"));
+        assert_eq!(actual, r"100%%\! main");
+    }
+
+    #[test]
+    fn test_render_escapes_value_in_bash() {
+        let branch = branch("$x");
+        let actual = render("{branch}", &Mode::Bash, &branch);
+        assert_eq!(actual, r"\\$x");
+    }
+
+    #[test]
+    fn test_render_backslash_escapes_syntax() {
+        let actual = render(r"\[{branch}\]\{\}", &Mode::Stdout, &branch("main"));
+        assert_eq!(actual, "[main]{}");
+    }
+
+    #[test]
+    fn test_render_status_is_empty_when_not_changed() {
+        let actual = render("{branch}[ {status}]", &Mode::Stdout, &branch("main"));
+        assert_eq!(actual, "main");
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_placeholder() {
+        assert_eq!(
+            "{nope}".parse::<Template>(),
+            Err(ParseTemplateError::UnknownPlaceholder("nope".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_color() {
+        assert!(matches!(
+            "{branch:purple}".parse::<Template>(),
+            Err(ParseTemplateError::UnknownColor(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_rejects_unbalanced_syntax() {
+        assert_eq!(
+            "{branch".parse::<Template>(),
+            Err(ParseTemplateError::UnclosedPlaceholder)
+        );
+        assert_eq!(
+            "[{branch}".parse::<Template>(),
+            Err(ParseTemplateError::UnclosedSection)
+        );
+        assert_eq!(
+            "{branch}]".parse::<Template>(),
+            Err(ParseTemplateError::UnmatchedBracket)
+        );
+        assert_eq!(
+            "{branch}}".parse::<Template>(),
+            Err(ParseTemplateError::UnmatchedBrace)
+        );
+        assert_eq!(
+            "{branch}\\".parse::<Template>(),
+            Err(ParseTemplateError::TrailingBackslash)
+        );
+    }
+}
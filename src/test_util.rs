@@ -0,0 +1,67 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fixtures shared by the unit tests.
+
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::Command;
+
+use crate::branch::{Branch, HeadKind, Status};
+
+/// A `git` command in `dir` that ignores the global and system config and
+/// pins the identity and signing, so fixtures are hermetic.
+pub fn git_command(dir: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(dir)
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .args([
+            "-c",
+            "user.name=tester",
+            "-c",
+            "user.email=tester@example.com",
+        ])
+        .args(["-c", "commit.gpgsign=false", "-c", "tag.gpgsign=false"]);
+    cmd
+}
+
+/// Run `git` in `dir` hermetically, asserting that it succeeds.
+pub fn git(dir: &Path, args: &[&str]) {
+    git_command(dir).args(args).assert().success();
+}
+
+/// Run `git` in `dir` hermetically and return its trimmed stdout.
+pub fn git_stdout(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = git_command(dir).args(args).assert().success();
+    Ok(String::from_utf8(output.get_output().stdout.clone())?
+        .trim()
+        .to_owned())
+}
+
+/// A clean branch with the given name, no action, upstream, stash or counts,
+/// for tests to override the fields they care about.
+pub fn branch(name: &str) -> Branch {
+    Branch {
+        name: name.to_owned(),
+        action: None,
+        progress: None,
+        head: HeadKind::Symbolic,
+        status: Status::NotChanged,
+        upstream: None,
+        stash: 0,
+        changes: None,
+    }
+}
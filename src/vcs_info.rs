@@ -0,0 +1,474 @@
+// Copyright 2021 Akiomi Kamakura
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Formats in the style of zsh's `vcs_info`, so its `formats` and
+//! `actionformats` styles can be reused as they are.
+//!
+//! Specifiers are expanded like `zformat` does: `%b` may be given a minimum
+//! and a maximum width as in `%-10.20b`, padding on the left with `-` and on
+//! the right otherwise, and any other sequence, such as `%F{red}` or `%%`, is
+//! kept for the shell's prompt expansion. Only the expanded values are
+//! escaped for the mode.
+
+use std::path::Path;
+
+use clap::Args;
+
+use crate::branch::Branch;
+use crate::mode::Mode;
+use crate::report::Report;
+
+/// The `formats` style of `vcs_info` when it is not set.
+const DEFAULT_FORMATS: &str = " (%s)-[%b]%u%c-";
+
+/// The `actionformats` style of `vcs_info` when it is not set.
+const DEFAULT_ACTIONFORMATS: &str = " (%s)-[%b|%a]%u%c-";
+
+/// The specifiers that are expanded, the rest being kept as they are.
+const SPECIFIERS: &str = "sbacuiRrSm";
+
+/// The `vcs_info` styles given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+#[non_exhaustive]
+pub struct Options {
+    /// Formats the output like the `formats` style of zsh's `vcs_info`, e.g.
+    /// ' (%s)-[%b]%u%c-'. Used when no action is in progress
+    #[arg(
+        long = "vcs-info-formats",
+        value_name = "FORMAT",
+        conflicts_with = "format"
+    )]
+    pub formats: Option<String>,
+
+    /// Formats the output like the `actionformats` style of zsh's
+    /// `vcs_info`, e.g. ' (%s)-[%b|%a]%u%c-'. Used during an action
+    #[arg(
+        long = "vcs-info-actionformats",
+        value_name = "FORMAT",
+        conflicts_with = "format"
+    )]
+    pub actionformats: Option<String>,
+
+    /// What `%c` expands to when there are staged changes
+    #[arg(
+        long = "vcs-info-stagedstr",
+        value_name = "STRING",
+        default_value = "S"
+    )]
+    pub stagedstr: String,
+
+    /// What `%u` expands to when there are unstaged changes
+    #[arg(
+        long = "vcs-info-unstagedstr",
+        value_name = "STRING",
+        default_value = "U"
+    )]
+    pub unstagedstr: String,
+}
+
+impl Options {
+    /// Whether the output is formatted with `vcs_info` formats, which is the
+    /// case once either of them is given.
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.formats.is_some() || self.actionformats.is_some()
+    }
+
+    /// Whether `%c` or `%u` is used, which requires counting the changes.
+    #[must_use]
+    pub fn needs_counts(&self) -> bool {
+        self.enabled()
+            && [self.formats(), self.actionformats()]
+                .into_iter()
+                .flat_map(pieces)
+                .any(|piece| {
+                    matches!(
+                        piece,
+                        Piece::Spec {
+                            spec: 'c' | 'u',
+                            ..
+                        }
+                    )
+                })
+    }
+
+    fn formats(&self) -> &str {
+        self.formats.as_deref().unwrap_or(DEFAULT_FORMATS)
+    }
+
+    fn actionformats(&self) -> &str {
+        self.actionformats
+            .as_deref()
+            .unwrap_or(DEFAULT_ACTIONFORMATS)
+    }
+}
+
+/// The values of the specifiers, unescaped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Values {
+    /// `%b`: the branch name.
+    pub branch: String,
+    /// `%a`: the in-progress action, e.g. `rebase-i`.
+    pub action: String,
+    /// `%c`: the `stagedstr` if there are staged changes.
+    pub staged: String,
+    /// `%u`: the `unstagedstr` if there are unstaged changes.
+    pub unstaged: String,
+    /// `%i`: the id of the commit HEAD points at.
+    pub revision: String,
+    /// `%R`: the root of the worktree.
+    pub root: String,
+    /// `%r`: the name of the root directory.
+    pub repository: String,
+    /// `%S`: the directory relative to the root, `.` at the root.
+    pub subdir: String,
+    /// `%m`: the progress of a rebase or `git am`, e.g. `3/12`.
+    pub misc: String,
+}
+
+impl Values {
+    /// The values for `report` read from `dir`.
+    #[must_use]
+    pub fn new(report: &Report, dir: &Path, options: &Options) -> Self {
+        let Branch {
+            name,
+            action,
+            progress,
+            changes,
+            ..
+        } = &report.branch;
+        let changes = changes.as_ref();
+        let staged = changes.is_some_and(|c| c.staged + c.conflicted > 0);
+        let unstaged = changes.is_some_and(|c| c.unstaged + c.deleted + c.conflicted > 0);
+        let root = report
+            .root
+            .as_deref()
+            .map(|root| root.canonicalize().unwrap_or_else(|_| root.to_path_buf()));
+        let subdir = root.as_deref().and_then(|root| {
+            let subdir = dir.canonicalize().ok()?;
+            let subdir = subdir.strip_prefix(root).ok()?;
+            Some(if subdir.as_os_str().is_empty() {
+                ".".to_owned()
+            } else {
+                subdir.to_string_lossy().into_owned()
+            })
+        });
+        Self {
+            branch: name.clone(),
+            action: action.clone().unwrap_or_default(),
+            staged: if staged {
+                options.stagedstr.clone()
+            } else {
+                String::new()
+            },
+            unstaged: if unstaged {
+                options.unstagedstr.clone()
+            } else {
+                String::new()
+            },
+            revision: report.revision.clone().unwrap_or_default(),
+            repository: root
+                .as_deref()
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            root: root
+                .map(|root| root.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            subdir: subdir.unwrap_or_default(),
+            misc: progress
+                .as_ref()
+                .map(|progress| format!("{}/{}", progress.current, progress.total))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The value of the specifier `spec`, one of [`SPECIFIERS`].
+    fn get(&self, spec: char) -> &str {
+        match spec {
+            's' => "git",
+            'b' => &self.branch,
+            'a' => &self.action,
+            'c' => &self.staged,
+            'u' => &self.unstaged,
+            'i' => &self.revision,
+            'R' => &self.root,
+            'r' => &self.repository,
+            'S' => &self.subdir,
+            'm' => &self.misc,
+            _ => "",
+        }
+    }
+}
+
+/// `report` formatted with `formats`, or `actionformats` during an action.
+/// `--mode json` and `--mode nuon` always print the whole document.
+#[must_use]
+pub fn format(mode: &Mode, report: &Report, dir: &Path, options: &Options) -> String {
+    if matches!(mode, Mode::Json | Mode::Nuon) {
+        return mode.format(&report.branch, None);
+    }
+    let values = Values::new(report, dir, options);
+    let format = if values.action.is_empty() {
+        options.formats()
+    } else {
+        options.actionformats()
+    };
+    render(format, &values, mode)
+}
+
+/// `format` with every specifier replaced by its escaped value.
+#[must_use]
+pub fn render(format: &str, values: &Values, mode: &Mode) -> String {
+    pieces(format)
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Literal(text) => text.to_owned(),
+            Piece::Spec {
+                right,
+                min,
+                max,
+                spec,
+            } => {
+                let value = values.get(spec);
+                let mut value: String = value.chars().take(max.unwrap_or(usize::MAX)).collect();
+                let padding = " ".repeat(min.saturating_sub(value.chars().count()));
+                if right {
+                    value.insert_str(0, &padding);
+                } else {
+                    value.push_str(&padding);
+                }
+                mode.escape(&value).into_owned()
+            }
+        })
+        .collect()
+}
+
+/// A part of a format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Piece<'a> {
+    /// Text to copy as it is, including unknown `%` sequences.
+    Literal(&'a str),
+    /// A specifier with its minimum and maximum width. `right` pads it on the
+    /// left.
+    Spec {
+        right: bool,
+        min: usize,
+        max: Option<usize>,
+        spec: char,
+    },
+}
+
+/// `format` split into literal text and specifiers.
+fn pieces(format: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('%') {
+        pieces.push(Piece::Literal(&rest[..start]));
+        let sequence = &rest[start + 1..];
+        let right = sequence.starts_with('-');
+        let sequence = sequence.strip_prefix('-').unwrap_or(sequence);
+        let (min, sequence) = number(sequence);
+        let (max, sequence) = sequence
+            .strip_prefix('.')
+            .map_or((None, sequence), |sequence| {
+                let (max, sequence) = number(sequence);
+                (Some(max), sequence)
+            });
+        let mut chars = sequence.chars();
+        match chars.next() {
+            Some(spec) if SPECIFIERS.contains(spec) => {
+                pieces.push(Piece::Spec {
+                    right,
+                    min,
+                    max,
+                    spec,
+                });
+                rest = chars.as_str();
+            }
+            // Anything else is kept for the prompt, e.g. `%F{red}` or `%%`,
+            // which must not be split so its second `%` is not read again.
+            Some(_) => {
+                let end = rest.len() - chars.as_str().len();
+                pieces.push(Piece::Literal(&rest[start..end]));
+                rest = chars.as_str();
+            }
+            None => {
+                pieces.push(Piece::Literal(&rest[start..]));
+                rest = "";
+            }
+        }
+    }
+    pieces.push(Piece::Literal(rest));
+    pieces.retain(|piece| *piece != Piece::Literal(""));
+    pieces
+}
+
+/// The decimal number at the start of `text`, 0 if there is none, and the
+/// text after it.
+fn number(text: &str) -> (usize, &str) {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    (text[..end].parse().unwrap_or(0), &text[end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::Result;
+    use assert_fs::TempDir;
+
+    use super::*;
+    use crate::branch::{Changes, Progress};
+    use crate::test_util::branch;
+
+    fn options(formats: Option<&str>, actionformats: Option<&str>) -> Options {
+        Options {
+            formats: formats.map(str::to_owned),
+            actionformats: actionformats.map(str::to_owned),
+            stagedstr: "S".to_owned(),
+            unstagedstr: "U".to_owned(),
+        }
+    }
+
+    fn values() -> Values {
+        Values {
+            branch: "main".to_owned(),
+            revision: "0123456789abcdef".to_owned(),
+            ..Values::default()
+        }
+    }
+
+    fn report(branch: Branch) -> Report {
+        Report {
+            branch,
+            complete: true,
+            color_ui: None,
+            revision: None,
+            root: None,
+            head_name: "main".to_owned(),
+            tag: None,
+        }
+    }
+
+    #[test]
+    fn test_render_replaces_specifiers() {
+        let values = Values {
+            staged: "S".to_owned(),
+            ..values()
+        };
+        assert_eq!(
+            render(" (%s)-[%b]%u%c-", &values, &Mode::Stdout),
+            " (git)-[main]S-"
+        );
+    }
+
+    #[test]
+    fn test_render_keeps_other_sequences() {
+        assert_eq!(
+            render("%F{green}%b%f 100%% %x%", &values(), &Mode::Zsh),
+            "%F{green}main%f 100%% %x%"
+        );
+    }
+
+    #[test]
+    fn test_render_applies_widths() {
+        assert_eq!(render("[%.7i]", &values(), &Mode::Stdout), "[0123456]");
+        assert_eq!(render("[%6b]", &values(), &Mode::Stdout), "[main  ]");
+        assert_eq!(render("[%-6b]", &values(), &Mode::Stdout), "[  main]");
+        assert_eq!(render("[%2.3b]", &values(), &Mode::Stdout), "[mai]");
+    }
+
+    #[test]
+    fn test_render_escapes_values_only() {
+        let values = Values {
+            branch: "100%".to_owned(),
+            ..values()
+        };
+        assert_eq!(render("%%%b", &values, &Mode::Zsh), "%%100%%");
+    }
+
+    #[test]
+    fn test_format_uses_actionformats_during_an_action() {
+        let options = options(Some("[%b]"), Some("[%b|%a %m]"));
+        let branch = branch("main");
+        assert_eq!(
+            format(
+                &Mode::Stdout,
+                &report(branch.clone()),
+                Path::new("."),
+                &options
+            ),
+            "[main]"
+        );
+        let branch = Branch {
+            action: Some("rebase-i".to_owned()),
+            progress: Some(Progress {
+                current: 3,
+                total: 12,
+            }),
+            ..branch
+        };
+        assert_eq!(
+            format(&Mode::Stdout, &report(branch), Path::new("."), &options),
+            "[main|rebase-i 3/12]"
+        );
+    }
+
+    #[test]
+    fn test_format_defaults_the_other_style_to_vcs_info() {
+        let options = options(None, Some("[%b|%a]"));
+        let branch = Branch {
+            changes: Some(Changes {
+                deleted: 1,
+                ..Changes::default()
+            }),
+            ..branch("main")
+        };
+        assert_eq!(
+            format(&Mode::Stdout, &report(branch), Path::new("."), &options),
+            " (git)-[main]U-"
+        );
+    }
+
+    #[test]
+    fn test_needs_counts_only_for_changes() {
+        assert!(!options(None, None).needs_counts());
+        assert!(!options(Some("%b"), Some("%b|%a")).needs_counts());
+        assert!(options(Some("%b%c"), Some("%b|%a")).needs_counts());
+        assert!(options(Some("%b"), None).needs_counts());
+        assert!(!options(Some("%b %%c"), Some("%b")).needs_counts());
+    }
+
+    #[test]
+    fn values_read_paths_relative_to_the_root() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path().join("repo");
+        fs::create_dir_all(root.join("src/bin"))?;
+        let report = Report {
+            root: Some(root.clone()),
+            ..report(branch(""))
+        };
+        let options = options(Some("%b"), None);
+        let values = Values::new(&report, &root.join("src/bin"), &options);
+        assert_eq!(values.repository, "repo");
+        assert_eq!(values.subdir, "src/bin");
+        assert_eq!(values.root, root.canonicalize()?.to_string_lossy());
+        assert_eq!(Values::new(&report, &root, &options).subdir, ".");
+        dir.close().map_err(Into::into)
+    }
+}
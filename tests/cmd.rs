@@ -1,5 +1,49 @@
-use anyhow::Result;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+#[cfg(unix)]
+use std::io::{BufRead as _, BufReader, Write as _};
+use std::iter;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
+use assert_cmd::cargo::cargo_bin;
 use assert_cmd::{Command, pkg_name};
+use assert_fs::TempDir;
+use assert_fs::prelude::*;
+#[cfg(unix)]
+use git_branch_status::branch::{Branch, HeadKind, Status};
+#[cfg(unix)]
+use git_branch_status::daemon::Response;
+#[cfg(unix)]
+use git_branch_status::report::Report;
+
+/// A `git` command in `dir` that ignores the global and system config and
+/// pins the identity and signing, so fixtures are hermetic.
+fn git_command(dir: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(dir)
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .args([
+            "-c",
+            "user.name=tester",
+            "-c",
+            "user.email=tester@example.com",
+        ])
+        .args(["-c", "commit.gpgsign=false", "-c", "tag.gpgsign=false"]);
+    cmd
+}
+
+/// Run `git` in `dir` hermetically, asserting that it succeeds.
+fn git(dir: &Path, args: &[&str]) {
+    git_command(dir).args(args).assert().success();
+}
 
 #[test]
 fn execute_success_without_dir() -> Result<()> {
@@ -24,3 +68,1142 @@ fn execute_failure_with_dir() -> Result<()> {
         .stderr("");
     Ok(())
 }
+
+#[test]
+fn execute_failure_with_invalid_format() -> Result<()> {
+    Command::cargo_bin(pkg_name!())?
+        .args(["--format", "{nope}"])
+        .assert()
+        .failure()
+        .code(2)
+        .stdout("");
+    Ok(())
+}
+
+#[test]
+fn execute_success_with_timeout() -> Result<()> {
+    let dir = staged_repo()?;
+    // A scan that completes in time shows the status, not `unknown`.
+    Command::cargo_bin(pkg_name!())?
+        .arg(dir.path())
+        .args(["--timeout", "60000"])
+        .assert()
+        .success()
+        .stdout("main+");
+    // One cut short still shows the branch, with the unknown marker.
+    let output = Command::cargo_bin(pkg_name!())?
+        .arg(dir.path())
+        .args(["--timeout", "0"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(["main+", "main…"].contains(&stdout.as_str()), "{stdout}");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_prints_name_and_fails_when_status_detection_fails() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    dir.child("f").write_str("a\n")?;
+    git(dir.path(), &["add", "f"]);
+    // An index that gix cannot parse makes the status scan fail, while HEAD,
+    // and thus the branch name, is still readable.
+    dir.child(".git/index").write_str(&"garbage".repeat(16))?;
+    Command::cargo_bin(pkg_name!())?
+        .args(["--mode", "zsh"])
+        .arg(dir.path())
+        .env_remove("NO_COLOR")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("%F{magenta}main%f")
+        .stderr("");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_init_prints_script() -> Result<()> {
+    Command::cargo_bin(pkg_name!())?
+        .args(["init", "fish"])
+        .assert()
+        .success()
+        .stdout(include_str!("../src/shell/init.fish"));
+    Ok(())
+}
+
+#[test]
+fn execute_init_accepts_pwsh_alias() -> Result<()> {
+    Command::cargo_bin(pkg_name!())?
+        .args(["init", "pwsh"])
+        .assert()
+        .success()
+        .stdout(include_str!("../src/shell/init.ps1"));
+    Ok(())
+}
+
+#[test]
+fn execute_init_prints_async_zsh_script() -> Result<()> {
+    Command::cargo_bin(pkg_name!())?
+        .args(["init", "zsh", "--async"])
+        .assert()
+        .success()
+        .stdout(concat!(
+            include_str!("../src/shell/init-async.zsh"),
+            "\n",
+            include_str!("../src/shell/in-repo.zsh"),
+        ));
+    Ok(())
+}
+
+#[test]
+fn execute_failure_with_async_init_for_bash() -> Result<()> {
+    Command::cargo_bin(pkg_name!())?
+        .args(["init", "bash", "--async"])
+        .assert()
+        .failure()
+        .code(2)
+        .stdout("");
+    Ok(())
+}
+
+#[test]
+fn execute_failure_with_unknown_init_shell() -> Result<()> {
+    Command::cargo_bin(pkg_name!())?
+        .args(["init", "ksh"])
+        .assert()
+        .failure()
+        .code(2)
+        .stdout("");
+    Ok(())
+}
+
+/// The path of `name` on `PATH`. Tests for shells that are not installed by
+/// default are `#[ignore]`d, and run with `cargo test -- --ignored`.
+fn find_shell(name: &str) -> Result<PathBuf> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| anyhow!("{name} is not on PATH"))
+}
+
+/// `PATH` with the built binary first, as the scripts call `git-branch-status`.
+fn path_with_binary() -> Result<OsString> {
+    let binary = cargo_bin(pkg_name!());
+    let dir = binary.parent().map(Path::to_path_buf).unwrap_or_default();
+    let path = env::var_os("PATH").unwrap_or_default();
+    let dirs = iter::once(dir).chain(env::split_paths(&path));
+    Ok(env::join_paths(dirs)?)
+}
+
+/// Run `script` in `shell` in `dir`, with the built binary on `PATH`.
+fn run_shell(shell: &Path, args: &[&str], script: &str, dir: &Path) -> Result<String> {
+    let output = Command::new(shell)
+        .args(args)
+        .arg(script)
+        .current_dir(dir)
+        .env("PATH", path_with_binary()?)
+        .env_remove("NO_COLOR")
+        .env_remove("GIT_DIR")
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn init_bash_shows_branch_in_ps1() -> Result<()> {
+    let bash = find_shell("bash")?;
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "x$(id)`id`"]);
+    let script = r#"PS1='repo> '; eval "$(git-branch-status init bash)"; eval "$PROMPT_COMMAND"; printf '%s' "${PS1@P}""#;
+    let actual = run_shell(&bash, &["--norc", "--noprofile", "-c"], script, dir.path())?;
+    assert_eq!(actual, "repo> \u{1b}[32mx$(id)`id`\u{1b}[39m ");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn init_bash_inserts_branch_before_prompt_sign() -> Result<()> {
+    let bash = find_shell("bash")?;
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    // The repository is found from a subdirectory, too.
+    let sub = dir.child("a/b");
+    sub.create_dir_all()?;
+    let script = r#"PS1='\w\$ '; eval "$(git-branch-status init bash)"; eval "$PROMPT_COMMAND"; printf '%s' "$PS1""#;
+    let actual = run_shell(&bash, &["--norc", "--noprofile", "-c"], script, sub.path())?;
+    assert_eq!(actual, r"\w \[\e[32m\]main\[\e[39m\]\$ ");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn init_bash_keeps_ps1_and_exit_status_outside_repository() -> Result<()> {
+    let bash = find_shell("bash")?;
+    let dir = TempDir::new()?;
+    // `false` sets the exit status that the prompt command must preserve.
+    let script = r#"PS1='\w\$ '; eval "$(git-branch-status init bash)"; false; eval "$PROMPT_COMMAND"; printf '%s %s' "$?" "$PS1""#;
+    let actual = run_shell(&bash, &["--norc", "--noprofile", "-c"], script, dir.path())?;
+    assert_eq!(actual, r"1 \w\$ ");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+#[ignore = "needs zsh on PATH"]
+fn init_zsh_shows_branch_in_rprompt() -> Result<()> {
+    let zsh = find_shell("zsh")?;
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "100%"]);
+    let script = r#"eval "$(git-branch-status init zsh)"; __git_branch_status_precmd; print -rn -- "${(%)${(e)RPROMPT}}""#;
+    let actual = run_shell(&zsh, &["-f", "-c"], script, dir.path())?;
+    assert_eq!(actual, "\u{1b}[32m100%\u{1b}[39m");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+#[ignore = "needs zsh on PATH"]
+fn init_zsh_async_shows_branch_in_rprompt() -> Result<()> {
+    let zsh = find_shell("zsh")?;
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    // Without a line editor, `zle` is stubbed and the callback is called
+    // directly with the descriptor `precmd` registered.
+    let script = r#"eval "$(git-branch-status init zsh --async)"; zle() { :; }; __git_branch_status_precmd; __git_branch_status_callback $__git_branch_status_fd; print -rn -- "${(%)${(e)RPROMPT}}""#;
+    let actual = run_shell(&zsh, &["-f", "-c"], script, dir.path())?;
+    assert_eq!(actual, "\u{1b}[32mmain\u{1b}[39m");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+#[ignore = "needs fish on PATH"]
+fn init_fish_shows_branch_in_right_prompt() -> Result<()> {
+    let fish = find_shell("fish")?;
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    let script = "git-branch-status init fish | source; fish_right_prompt";
+    let actual = run_shell(&fish, &["--no-config", "-c"], script, dir.path())?;
+    assert_eq!(actual, "\u{1b}[32mmain\u{1b}[39m");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_uses_mode_from_env() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    Command::cargo_bin(pkg_name!())?
+        .arg(dir.path())
+        .env("GIT_BRANCH_STATUS_MODE", "zsh")
+        .env_remove("NO_COLOR")
+        .assert()
+        .success()
+        .stdout("%F{green}main%f");
+    dir.close().map_err(Into::into)
+}
+
+/// `script` running `command` with `sh` on a new terminal, since piped output
+/// is never detected. The shell variables are removed, so detection only
+/// sees what `command` sets up.
+#[cfg(target_os = "linux")]
+fn on_terminal(command: &str) -> Command {
+    let mut script = Command::new("script");
+    script
+        .args(["-qec", command, "/dev/null"])
+        .env_remove("ZSH_VERSION")
+        .env_remove("BASH_VERSION")
+        .env_remove("FISH_VERSION")
+        .env_remove("NU_VERSION")
+        .env_remove("PSModulePath")
+        .env("SHELL", "/bin/sh")
+        .env_remove("NO_COLOR");
+    script
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn execute_auto_detects_login_shell() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    // The parent is `script`, so detection falls through to `$SHELL`.
+    let command = format!(
+        "exec env SHELL=/usr/bin/tcsh '{}' --mode auto '{}'",
+        cargo_bin(pkg_name!()).display(),
+        dir.path().display()
+    );
+    on_terminal(&command)
+        .assert()
+        .success()
+        .stdout("%{\x1b[32m%}main%{\x1b[39m%}");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_auto_prints_plain_output_when_piped() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    Command::cargo_bin(pkg_name!())?
+        .args(["--mode", "auto"])
+        .arg(dir.path())
+        .env("BASH_VERSION", "5.2.15(1)-release")
+        .env_remove("NO_COLOR")
+        .env_remove("CLICOLOR_FORCE")
+        .assert()
+        .success()
+        .stdout("main");
+    dir.close().map_err(Into::into)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn execute_auto_detects_shell_through_git() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    // The trailing `true` keeps bash from replacing itself with git, so the
+    // process tree is bash -> git -> git-branch-status.
+    on_terminal("bash --norc --noprofile -c 'git branch-status --mode auto; true'")
+        .current_dir(dir.path())
+        .env("PATH", path_with_binary()?)
+        .assert()
+        .success()
+        .stdout(r"\[\e[32m\]main\[\e[39m\]");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_fish_uses_color_exported_by_function() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    Command::cargo_bin(pkg_name!())?
+        .args(["--mode", "fish"])
+        .arg(dir.path())
+        .env("__git_branch_status_color_not_changed", "\x1b[1;92m")
+        .env_remove("NO_COLOR")
+        .assert()
+        .success()
+        .stdout("\x1b[1;92mmain\x1b[0m");
+    dir.close().map_err(Into::into)
+}
+
+/// A repository on `main` with a staged file.
+fn staged_repo() -> Result<TempDir> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    dir.child("f").write_str("a\n")?;
+    git(dir.path(), &["add", "f"]);
+    Ok(dir)
+}
+
+#[test]
+fn execute_without_color_when_piped() -> Result<()> {
+    let dir = staged_repo()?;
+    Command::cargo_bin(pkg_name!())?
+        .arg(dir.path())
+        .env_remove("NO_COLOR")
+        .env_remove("CLICOLOR_FORCE")
+        .assert()
+        .success()
+        .stdout("main+");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_with_color_when_forced() -> Result<()> {
+    let dir = staged_repo()?;
+    Command::cargo_bin(pkg_name!())?
+        .arg(dir.path())
+        .env_remove("NO_COLOR")
+        .env("CLICOLOR_FORCE", "1")
+        .assert()
+        .success()
+        .stdout("\x1b[33mmain\x1b[39m");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_honors_no_color_in_prompt_mode() -> Result<()> {
+    let dir = staged_repo()?;
+    Command::cargo_bin(pkg_name!())?
+        .args(["--mode", "zsh"])
+        .arg(dir.path())
+        .env("NO_COLOR", "1")
+        .assert()
+        .success()
+        .stdout("main+");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_honors_color_ui_never() -> Result<()> {
+    let dir = staged_repo()?;
+    git(dir.path(), &["config", "color.ui", "never"]);
+    Command::cargo_bin(pkg_name!())?
+        .args(["--mode", "zsh"])
+        .arg(dir.path())
+        .env_remove("NO_COLOR")
+        .env_remove("CLICOLOR_FORCE")
+        .assert()
+        .success()
+        .stdout("main+");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_color_flag_overrides_no_color() -> Result<()> {
+    let dir = staged_repo()?;
+    Command::cargo_bin(pkg_name!())?
+        .args(["--mode", "zsh", "--color", "always"])
+        .arg(dir.path())
+        .env("NO_COLOR", "1")
+        .assert()
+        .success()
+        .stdout("%F{yellow}main%f");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_with_colors_flag() -> Result<()> {
+    let dir = staged_repo()?;
+    Command::cargo_bin(pkg_name!())?
+        .args(["--mode", "zsh", "--colors", "staged=136"])
+        .arg(dir.path())
+        .env_remove("NO_COLOR")
+        .env_remove("GIT_BRANCH_STATUS_COLORS")
+        .assert()
+        .success()
+        .stdout("%F{136}main%f");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_uses_colors_from_env() -> Result<()> {
+    let dir = staged_repo()?;
+    Command::cargo_bin(pkg_name!())?
+        .args(["--mode", "bash"])
+        .arg(dir.path())
+        .env_remove("NO_COLOR")
+        .env("GIT_BRANCH_STATUS_COLORS", "staged=#b58900")
+        .assert()
+        .success()
+        .stdout(r"\[\e[38;2;181;137;0m\]main\[\e[39m\]");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_failure_with_invalid_colors() -> Result<()> {
+    Command::cargo_bin(pkg_name!())?
+        .args(["--colors", "staged=orange"])
+        .assert()
+        .failure()
+        .code(2);
+    Ok(())
+}
+
+/// A `daemon` process, killed when dropped.
+struct Daemon(Child);
+
+impl Daemon {
+    /// Start a daemon on `socket` and wait until it listens.
+    fn spawn(socket: &Path) -> Result<Self> {
+        let child = process::Command::new(cargo_bin(pkg_name!()))
+            .arg("daemon")
+            .arg("--socket")
+            .arg(socket)
+            .stderr(Stdio::null())
+            .spawn()?;
+        let daemon = Self(child);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !socket.exists() {
+            if Instant::now() > deadline {
+                bail!("the daemon did not start");
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        Ok(daemon)
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        self.0.kill().ok();
+        self.0.wait().ok();
+    }
+}
+
+/// The output of a plain run in `dir`, asking the daemon on `socket`.
+fn output_with_socket(dir: &Path, socket: &Path) -> Result<String> {
+    let output = Command::cargo_bin(pkg_name!())?
+        .arg(dir)
+        .env("GIT_BRANCH_STATUS_SOCKET", socket)
+        .env_remove("NO_COLOR")
+        .env_remove("CLICOLOR_FORCE")
+        .output()?;
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn daemon_answers_and_follows_changes() -> Result<()> {
+    let dir = staged_repo()?;
+    let run = TempDir::new()?;
+    let socket = run.path().join("daemon.sock");
+    let daemon = Daemon::spawn(&socket)?;
+    assert_eq!(output_with_socket(dir.path(), &socket)?, "main+");
+
+    dir.child("f").write_str("b\n")?;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while output_with_socket(dir.path(), &socket)? != "main*" {
+        if Instant::now() > deadline {
+            bail!("the change was not picked up");
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    drop(daemon);
+    run.close()?;
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn daemon_fails_when_another_is_running() -> Result<()> {
+    let run = TempDir::new()?;
+    let socket = run.path().join("daemon.sock");
+    let daemon = Daemon::spawn(&socket)?;
+    Command::cargo_bin(pkg_name!())?
+        .arg("daemon")
+        .arg("--socket")
+        .arg(&socket)
+        .assert()
+        .failure();
+    drop(daemon);
+    run.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_falls_back_when_daemon_is_gone() -> Result<()> {
+    let dir = staged_repo()?;
+    let run = TempDir::new()?;
+    let socket = run.path().join("daemon.sock");
+    drop(Daemon::spawn(&socket)?);
+    // The killed daemon leaves its socket behind.
+    assert!(socket.exists());
+    assert_eq!(output_with_socket(dir.path(), &socket)?, "main+");
+    run.close()?;
+    dir.close().map_err(Into::into)
+}
+
+#[cfg(unix)]
+#[test]
+fn execute_falls_back_when_daemon_hangs() -> Result<()> {
+    let dir = staged_repo()?;
+    let run = TempDir::new()?;
+    let socket = run.path().join("daemon.sock");
+    // A listener that accepts connections but never answers.
+    let _listener = UnixListener::bind(&socket)?;
+    let started = Instant::now();
+    assert_eq!(output_with_socket(dir.path(), &socket)?, "main+");
+    assert!(started.elapsed() < Duration::from_secs(5));
+    run.close()?;
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_fails_outside_repository_with_daemon() -> Result<()> {
+    let dir = TempDir::new()?;
+    let run = TempDir::new()?;
+    let socket = run.path().join("daemon.sock");
+    let daemon = Daemon::spawn(&socket)?;
+    Command::cargo_bin(pkg_name!())?
+        .arg(dir.path())
+        .env("GIT_BRANCH_STATUS_SOCKET", &socket)
+        .env_remove("GIT_DIR")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("");
+    drop(daemon);
+    run.close()?;
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn gitstatusd_answers_requests_on_stdin() -> Result<()> {
+    let dir = staged_repo()?;
+    let request = format!("}}hello\x1f\x1eid\x1f{}\x1e", dir.path().display());
+    let output = Command::cargo_bin(pkg_name!())?
+        .args([
+            "gitstatusd",
+            "--parent-pid=1",
+            "--num-threads=4",
+            "-s",
+            "-1",
+        ])
+        .write_stdin(request)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let responses: Vec<Vec<&str>> = stdout
+        .split_terminator('\x1e')
+        .map(|response| response.split('\x1f').collect())
+        .collect();
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[0], ["}hello", "0"]);
+    assert_eq!(responses[1].len(), 29);
+    // The branch, and one staged file in an index of one.
+    assert_eq!(responses[1][4], "main");
+    assert_eq!(responses[1][9..11], ["1", "1"]);
+    dir.close().map_err(Into::into)
+}
+
+/// `porcelain` in `dir`, with `config` (`key=value`) on top of the repository
+/// config.
+fn porcelain(dir: &Path, config: &[(&str, &str)]) -> Result<Command> {
+    let mut cmd = Command::cargo_bin(pkg_name!())?;
+    cmd.current_dir(dir)
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_COUNT", config.len().to_string());
+    for (i, (key, value)) in config.iter().enumerate() {
+        cmd.env(format!("GIT_CONFIG_KEY_{i}"), key)
+            .env(format!("GIT_CONFIG_VALUE_{i}"), value);
+    }
+    cmd.arg("porcelain");
+    Ok(cmd)
+}
+
+/// Assert that `porcelain` in `dir` prints what `git status` prints, with
+/// rename detection turned off so any change can be compared.
+fn assert_porcelain_matches_git(dir: &Path) -> Result<()> {
+    let expected = git_command(dir)
+        .args(["-c", "status.renames=false"])
+        .args(["status", "--porcelain=v2", "--branch"])
+        .output()?;
+    assert!(expected.status.success());
+    let actual = porcelain(dir, &[("status.renames", "false")])?.output()?;
+    assert!(actual.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&actual.stdout),
+        String::from_utf8_lossy(&expected.stdout),
+        "in {}",
+        dir.display()
+    );
+    Ok(())
+}
+
+/// A xorshift generator, so the random worktrees are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        usize::try_from(self.0 % n as u64).unwrap_or_default()
+    }
+}
+
+#[test]
+fn porcelain_matches_git_on_random_worktrees() -> Result<()> {
+    let names = [
+        "a",
+        "b.txt",
+        "dir/c",
+        "dir/sub/d",
+        "other/e",
+        "with space",
+        "quote\"d",
+        "caf\u{e9}",
+    ];
+    for seed in [1, 7, 42, 1234, 99_999] {
+        let mut rng = Rng(seed);
+        let dir = TempDir::new()?;
+        git(dir.path(), &["init", "-q", "-b", "main"]);
+        for name in names {
+            dir.child(name).write_str(name)?;
+        }
+        git(dir.path(), &["add", "-A"]);
+        git(dir.path(), &["commit", "-q", "-m", "initial"]);
+        for step in 0..12 {
+            let name = names[rng.below(names.len())];
+            let file = dir.child(name);
+            let exists = file.path().exists();
+            match rng.below(7) {
+                0 | 1 if exists => file.write_str(&format!("{name} {step}\n"))?,
+                2 if exists => fs::remove_file(file.path())?,
+                3 if exists => git(dir.path(), &["add", "--", name]),
+                4 => git(
+                    dir.path(),
+                    &["rm", "-q", "--cached", "--ignore-unmatch", "--", name],
+                ),
+                5 => dir.child(format!("new{step}/n")).write_str("untracked\n")?,
+                6 => {
+                    dir.child(format!("{name}.new")).write_str("new\n")?;
+                    if rng.below(2) == 0 {
+                        git(dir.path(), &["add", "-N", "--", &format!("{name}.new")]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        assert_porcelain_matches_git(dir.path())?;
+        assert_porcelain_matches_git(&dir.path().join("dir"))?;
+        dir.close()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn porcelain_lists_untracked_files_beside_deleted_tracked_files() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    dir.child("dir/tracked").write_str("tracked\n")?;
+    git(dir.path(), &["add", "-A"]);
+    git(dir.path(), &["commit", "-q", "-m", "initial"]);
+    fs::remove_file(dir.child("dir/tracked").path())?;
+    dir.child("dir/untracked").write_str("untracked\n")?;
+    dir.child("dir/nested/untracked").write_str("untracked\n")?;
+    assert_porcelain_matches_git(dir.path())?;
+    dir.close().map_err(Into::into)
+}
+
+#[cfg(unix)]
+#[test]
+fn porcelain_matches_git_on_mode_and_type_changes() -> Result<()> {
+    use std::os::unix::fs::{PermissionsExt as _, symlink};
+
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    dir.child("exe").write_str("exe\n")?;
+    dir.child("link").write_str("link\n")?;
+    git(dir.path(), &["add", "-A"]);
+    git(dir.path(), &["commit", "-q", "-m", "initial"]);
+    fs::set_permissions(dir.child("exe").path(), fs::Permissions::from_mode(0o755))?;
+    fs::remove_file(dir.child("link").path())?;
+    symlink("exe", dir.child("link").path())?;
+    assert_porcelain_matches_git(dir.path())?;
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn porcelain_matches_git_on_conflicts_and_upstream() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    for name in ["both", "deleted-by-them", "deleted-by-us"] {
+        dir.child(name).write_str("base\n")?;
+    }
+    git(dir.path(), &["add", "-A"]);
+    git(dir.path(), &["commit", "-q", "-m", "base"]);
+    git(dir.path(), &["checkout", "-q", "-b", "other"]);
+    dir.child("both").write_str("other\n")?;
+    dir.child("added").write_str("other\n")?;
+    dir.child("deleted-by-us").write_str("other\n")?;
+    git(dir.path(), &["rm", "-q", "deleted-by-them"]);
+    git(dir.path(), &["add", "-A"]);
+    git(dir.path(), &["commit", "-q", "-m", "other"]);
+    git(dir.path(), &["checkout", "-q", "main"]);
+    dir.child("both").write_str("main\n")?;
+    dir.child("added").write_str("main\n")?;
+    dir.child("deleted-by-them").write_str("main\n")?;
+    git(dir.path(), &["rm", "-q", "deleted-by-us"]);
+    git(dir.path(), &["add", "-A"]);
+    git(dir.path(), &["commit", "-q", "-m", "main"]);
+    git(
+        dir.path(),
+        &["remote", "add", "origin", "https://example.com/repo.git"],
+    );
+    git(
+        dir.path(),
+        &["update-ref", "refs/remotes/origin/main", "other"],
+    );
+    git(
+        dir.path(),
+        &["branch", "-q", "--set-upstream-to", "origin/main"],
+    );
+    git_command(dir.path())
+        .args(["merge", "-q", "other"])
+        .assert()
+        .failure();
+    assert_porcelain_matches_git(dir.path())?;
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn porcelain_refuses_to_detect_renames() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    dir.child("a").write_str("a\n")?;
+    git(dir.path(), &["add", "a"]);
+    git(dir.path(), &["commit", "-q", "-m", "a"]);
+    git(dir.path(), &["mv", "a", "b"]);
+    assert_porcelain_matches_git(dir.path())?;
+    for (key, value) in [("status.renames", "true"), ("diff.renames", "copies")] {
+        porcelain(dir.path(), &[(key, value)])?
+            .assert()
+            .failure()
+            .code(1)
+            .stdout("")
+            .stderr("error: rename detection is not supported; set status.renames to false\n");
+    }
+    dir.close().map_err(Into::into)
+}
+
+/// `git status --porcelain=v2 --branch` in `dir` with the default config.
+fn git_porcelain(dir: &Path) -> Result<String> {
+    let output = git_command(dir)
+        .args(["status", "--porcelain=v2", "--branch"])
+        .output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn porcelain_matches_git_with_default_rename_detection() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    dir.child("a").write_str("a\n")?;
+    dir.child("c").write_str("c\n")?;
+    git(dir.path(), &["add", "-A"]);
+    git(dir.path(), &["commit", "-q", "-m", "initial"]);
+
+    // Nothing to pair up: the same output as git.
+    dir.child("a").write_str("changed\n")?;
+    dir.child("new").write_str("new\n")?;
+    git(dir.path(), &["add", "-A"]);
+    fs::remove_file(dir.child("c").path())?;
+    porcelain(dir.path(), &[])?
+        .assert()
+        .success()
+        .stdout(git_porcelain(dir.path())?);
+    git(dir.path(), &["reset", "-q", "--hard"]);
+
+    // A staged rename, and one in the worktree through `git add -N`.
+    git(dir.path(), &["mv", "a", "b"]);
+    assert!(git_porcelain(dir.path())?.contains("\n2 R. N... "));
+    porcelain(dir.path(), &[])?
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr("error: rename detection is not supported; set status.renames to false\n");
+    git(dir.path(), &["reset", "-q", "--hard"]);
+    fs::rename(dir.child("c").path(), dir.child("d").path())?;
+    git(dir.path(), &["add", "-N", "d"]);
+    assert!(git_porcelain(dir.path())?.contains("\n2 .R N... "));
+    porcelain(dir.path(), &[])?
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn porcelain_takes_dir_before_or_after_the_subcommand() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    dir.child("sub/f").write_str("f\n")?;
+    let expected = git_porcelain(&dir.path().join("sub"))?;
+    for args in [["porcelain", "sub"], ["sub", "porcelain"]] {
+        Command::cargo_bin(pkg_name!())?
+            .current_dir(dir.path())
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .args(args)
+            .assert()
+            .success()
+            .stdout(expected.clone());
+    }
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn porcelain_fails_outside_repository() -> Result<()> {
+    let dir = TempDir::new()?;
+    Command::cargo_bin(pkg_name!())?
+        .args(["porcelain"])
+        .arg(dir.path())
+        .env("GIT_CEILING_DIRECTORIES", dir.path())
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("");
+    dir.close().map_err(Into::into)
+}
+
+const GIT_PS1_VARS: [(&str, &str); 4] = [
+    ("GIT_PS1_SHOWDIRTYSTATE", "1"),
+    ("GIT_PS1_SHOWSTASHSTATE", "1"),
+    ("GIT_PS1_SHOWUNTRACKEDFILES", "1"),
+    ("GIT_PS1_SHOWUPSTREAM", "auto"),
+];
+
+#[test]
+fn ps1_shows_state_like_git_prompt() -> Result<()> {
+    let dir = staged_repo()?;
+    git(dir.path(), &["commit", "-q", "-m", "initial"]);
+    dir.child("f").write_str("b\n")?;
+    dir.child("g").write_str("g\n")?;
+    Command::cargo_bin(pkg_name!())?
+        .current_dir(dir.path())
+        .arg("ps1")
+        .envs(GIT_PS1_VARS)
+        .assert()
+        .success()
+        .stdout(" (main *%)");
+    Command::cargo_bin(pkg_name!())?
+        .current_dir(dir.path())
+        .args(["ps1", "[%s] "])
+        // Empty values turn the state off, like in `git-prompt.sh`.
+        .envs(GIT_PS1_VARS.map(|(key, _)| (key, "")))
+        .assert()
+        .success()
+        .stdout("[main] ");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn ps1_escapes_percent_for_zsh() -> Result<()> {
+    let dir = staged_repo()?;
+    dir.child("g").write_str("g\n")?;
+    Command::cargo_bin(pkg_name!())?
+        .current_dir(dir.path())
+        .args(["--mode", "zsh", "ps1"])
+        .envs(GIT_PS1_VARS)
+        .assert()
+        .success()
+        .stdout(" (main +%%)");
+    dir.close().map_err(Into::into)
+}
+
+#[cfg(unix)]
+#[test]
+fn ps1_asks_the_daemon() -> Result<()> {
+    let dir = staged_repo()?;
+    let run = TempDir::new()?;
+    let socket = run.path().join("daemon.sock");
+    let listener = UnixListener::bind(&socket)?;
+    // A daemon that answers with a branch the repository does not have.
+    let daemon = thread::spawn(move || -> Result<String> {
+        let (mut stream, _) = listener.accept()?;
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+        let report = Report {
+            branch: Branch {
+                name: "from-daemon".to_owned(),
+                action: None,
+                progress: None,
+                head: HeadKind::Symbolic,
+                status: Status::NotChanged,
+                upstream: None,
+                stash: 0,
+                changes: None,
+            },
+            complete: true,
+            color_ui: None,
+            revision: None,
+            root: None,
+            head_name: "from-daemon".to_owned(),
+            tag: None,
+        };
+        let mut line = serde_json::to_string(&Response::Report(Box::new(report)))?;
+        line.push('\n');
+        stream.write_all(line.as_bytes())?;
+        Ok(request)
+    });
+    Command::cargo_bin(pkg_name!())?
+        .current_dir(dir.path())
+        .arg("ps1")
+        .env("GIT_BRANCH_STATUS_SOCKET", &socket)
+        .envs(GIT_PS1_VARS)
+        .assert()
+        .success()
+        .stdout(" (from-daemon)");
+    let request = daemon
+        .join()
+        .map_err(|_| anyhow!("the daemon panicked"))??;
+    // The `GIT_PS1_*` variables decide what the daemon is asked for.
+    assert!(request.contains(r#""untracked_files":true"#), "{request}");
+    run.close()?;
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn ps1_prints_nothing_outside_repository() -> Result<()> {
+    let dir = TempDir::new()?;
+    Command::cargo_bin(pkg_name!())?
+        .current_dir(dir.path())
+        .arg("ps1")
+        .env("GIT_CEILING_DIRECTORIES", dir.path())
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("");
+    dir.close().map_err(Into::into)
+}
+
+/// Assert that `ps1` in `dir` prints what `__git_ps1` prints, if git ships
+/// `git-prompt.sh` where it can be found.
+fn assert_ps1_matches_git(dir: &Path) -> Result<()> {
+    assert_ps1_format_matches_git(dir, " (%s)")
+}
+
+/// Assert that `ps1 <format>` in `dir` prints what `__git_ps1 <format>`
+/// prints, if git ships `git-prompt.sh` where it can be found.
+fn assert_ps1_format_matches_git(dir: &Path, format: &str) -> Result<()> {
+    let Some(script) = [
+        "/usr/lib/git-core/git-sh-prompt",
+        "/usr/share/git-core/contrib/completion/git-prompt.sh",
+    ]
+    .into_iter()
+    .map(Path::new)
+    .find(|script| script.exists()) else {
+        return Ok(());
+    };
+    let expected = Command::new("bash")
+        .current_dir(dir)
+        .arg("-c")
+        .arg(r#"source "$1" && __git_ps1 "$2""#)
+        .arg("bash")
+        .arg(script)
+        .arg(format)
+        .envs(GIT_PS1_VARS)
+        .output()?;
+    let actual = Command::cargo_bin(pkg_name!())?
+        .current_dir(dir)
+        .args(["ps1", format])
+        .envs(GIT_PS1_VARS)
+        .output()?;
+    assert_eq!(
+        String::from_utf8_lossy(&actual.stdout),
+        String::from_utf8_lossy(&expected.stdout)
+    );
+    Ok(())
+}
+
+#[test]
+fn ps1_matches_git_prompt() -> Result<()> {
+    let dir = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    assert_ps1_matches_git(dir.path())?;
+    dir.child("f").write_str("a\n")?;
+    git(dir.path(), &["add", "f"]);
+    assert_ps1_matches_git(dir.path())?;
+    git(dir.path(), &["commit", "-q", "-m", "initial"]);
+    git(
+        dir.path(),
+        &["remote", "add", "origin", "https://example.com/repo.git"],
+    );
+    git(
+        dir.path(),
+        &["update-ref", "refs/remotes/origin/main", "HEAD"],
+    );
+    git(
+        dir.path(),
+        &["branch", "-q", "--set-upstream-to", "origin/main"],
+    );
+    git(
+        dir.path(),
+        &["commit", "-q", "--allow-empty", "-m", "ahead"],
+    );
+    dir.child("f").write_str("b\n")?;
+    git(dir.path(), &["stash", "-q"]);
+    dir.child("f").write_str("c\n")?;
+    git(dir.path(), &["add", "f"]);
+    dir.child("f").write_str("d\n")?;
+    dir.child("untracked").write_str("u\n")?;
+    assert_ps1_matches_git(dir.path())?;
+    git(dir.path(), &["checkout", "-q", "--detach"]);
+    assert_ps1_matches_git(dir.path())?;
+    git(dir.path(), &["tag", "v1"]);
+    assert_ps1_matches_git(dir.path())?;
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn ps1_expands_format_like_git_prompt() -> Result<()> {
+    let dir = staged_repo()?;
+    for format in ["(%s) 100%%", "%%s [%s]", "%s|%s"] {
+        assert_ps1_format_matches_git(dir.path(), format)?;
+    }
+    Command::cargo_bin(pkg_name!())?
+        .current_dir(dir.path())
+        .args(["ps1", "(%s) 100%%"])
+        .assert()
+        .success()
+        .stdout("(main) 100%");
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn ps1_matches_git_prompt_during_am_on_detached_head() -> Result<()> {
+    let dir = TempDir::new()?;
+    let patches = TempDir::new()?;
+    git(dir.path(), &["init", "-q", "-b", "main"]);
+    dir.child("f").write_str("a\n")?;
+    git(dir.path(), &["add", "f"]);
+    git(dir.path(), &["commit", "-q", "-m", "initial"]);
+    dir.child("f").write_str("b\n")?;
+    git(dir.path(), &["commit", "-q", "-am", "patched"]);
+    let output = patches.path().to_string_lossy().into_owned();
+    git(dir.path(), &["format-patch", "-q", "-1", "-o", &output]);
+    git(dir.path(), &["reset", "-q", "--hard", "HEAD~"]);
+    dir.child("f").write_str("c\n")?;
+    git(dir.path(), &["commit", "-q", "-am", "conflicting"]);
+    git(dir.path(), &["checkout", "-q", "--detach"]);
+
+    // `git am` stops at the conflicting patch, on the detached HEAD.
+    let patch = patches.path().join("0001-patched.patch");
+    git_command(dir.path())
+        .arg("am")
+        .arg(&patch)
+        .assert()
+        .failure();
+    assert_ps1_matches_git(dir.path())?;
+    git(dir.path(), &["am", "--abort"]);
+
+    // Neither `rebasing` nor `applying`: `git-prompt.sh` shows HEAD rather
+    // than the branch in `head-name`.
+    dir.child(".git/rebase-apply/head-name")
+        .write_str("refs/heads/main\n")?;
+    dir.child(".git/rebase-apply/next").write_str("1\n")?;
+    dir.child(".git/rebase-apply/last").write_str("2\n")?;
+    assert_ps1_matches_git(dir.path())?;
+    git(dir.path(), &["tag", "v1"]);
+    assert_ps1_matches_git(dir.path())?;
+    patches.close()?;
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_with_vcs_info_formats() -> Result<()> {
+    let dir = staged_repo()?;
+    dir.child("f").write_str("b\n")?;
+    dir.child("sub").create_dir_all()?;
+    Command::cargo_bin(pkg_name!())?
+        .args([
+            "--mode",
+            "zsh",
+            "--vcs-info-formats",
+            "%F{green}[%b]%f%u%c %r/%S",
+            "--vcs-info-unstagedstr",
+            "*",
+        ])
+        .arg(dir.child("sub").path())
+        .assert()
+        .success()
+        .stdout(format!(
+            "%F{{green}}[main]%f*S {}/sub",
+            dir.path()
+                .canonicalize()?
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+        ));
+    dir.close().map_err(Into::into)
+}
+
+#[test]
+fn execute_failure_with_format_and_vcs_info_formats() -> Result<()> {
+    Command::cargo_bin(pkg_name!())?
+        .args(["--format", "{branch}", "--vcs-info-formats", "%b"])
+        .assert()
+        .failure()
+        .code(2)
+        .stdout("");
+    Ok(())
+}